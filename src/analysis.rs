@@ -0,0 +1,342 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! This module provides tools for analysing the Tanner graph of each code.
+//!
+//! The Tanner graph has a node for each of the n + punctured_bits variables and each of the
+//! n + punctured_bits - k parity checks, with an edge wherever the parity check matrix has a
+//! one. Its degree distributions and short cycles determine how well message passing decoders
+//! perform: in particular, short cycles make the decoder's messages correlated, and the
+//! girth (the length of the shortest cycle) bounds how many iterations pass before this
+//! happens.
+//!
+//! `TannerGraph` expands the graph from `iter_paritychecks` into adjacency lists, so requires
+//! the `std` feature.
+//!
+//! ```
+//! # use labrador_ldpc::LDPCCode;
+//! use labrador_ldpc::analysis::TannerGraph;
+//!
+//! let graph = TannerGraph::new(LDPCCode::TC128);
+//! assert_eq!(graph.variable_degrees(), vec![0, 0, 0, 64, 0, 64]);
+//! assert_eq!(graph.count_cycles(6), 2336);
+//! assert_eq!(graph.girth(), Some(6));
+//! ```
+
+use std::vec::Vec;
+
+use ::codes::LDPCCode;
+
+/// Tanner graph of a code, held as adjacency lists.
+#[derive(Clone,Debug)]
+pub struct TannerGraph {
+    code: LDPCCode,
+    var_checks: Vec<Vec<usize>>,
+    check_vars: Vec<Vec<usize>>,
+}
+
+impl TannerGraph {
+    /// Build the Tanner graph of `code`.
+    pub fn new(code: LDPCCode) -> TannerGraph {
+        let vars = code.n() + code.punctured_bits();
+        let checks = vars - code.k();
+        let mut var_checks = vec![Vec::new(); vars];
+        let mut check_vars = vec![Vec::new(); checks];
+        for (check, var) in code.iter_paritychecks() {
+            var_checks[var].push(check);
+            check_vars[check].push(var);
+        }
+        TannerGraph { code, var_checks, check_vars }
+    }
+
+    /// Get the code this graph was built from.
+    pub fn code(&self) -> LDPCCode {
+        self.code
+    }
+
+    /// Get the parity checks connected to variable `var`.
+    pub fn checks_of(&self, var: usize) -> &[usize] {
+        &self.var_checks[var]
+    }
+
+    /// Get the variables connected to parity check `check`.
+    pub fn variables_of(&self, check: usize) -> &[usize] {
+        &self.check_vars[check]
+    }
+
+    /// Get the variable degree distribution, where element `d` is the number of variables
+    /// with degree `d`.
+    pub fn variable_degrees(&self) -> Vec<usize> {
+        histogram(self.var_checks.iter())
+    }
+
+    /// Get the check degree distribution, where element `d` is the number of checks with
+    /// degree `d`.
+    pub fn check_degrees(&self) -> Vec<usize> {
+        histogram(self.check_vars.iter())
+    }
+
+    /// Get the degree distribution of just the punctured variables, which are the last
+    /// punctured_bits variables.
+    pub fn punctured_degrees(&self) -> Vec<usize> {
+        histogram(self.var_checks[self.code.n()..].iter())
+    }
+
+    /// Get the girth, the length of the shortest cycle, or None if the graph has no cycles.
+    pub fn girth(&self) -> Option<usize> {
+        // Breadth-first search from every variable finds the shortest cycle through it, and
+        // each search can stop once it is deeper than the shortest cycle found so far.
+        let nodes = self.var_checks.len() + self.check_vars.len();
+        let mut depth = vec![usize::MAX; nodes];
+        let mut parent = vec![usize::MAX; nodes];
+        let mut visited = Vec::new();
+        let mut queue = Vec::new();
+        let mut girth = usize::MAX;
+
+        for start in 0..self.var_checks.len() {
+            for &node in &visited {
+                depth[node] = usize::MAX;
+                parent[node] = usize::MAX;
+            }
+            visited.clear();
+            queue.clear();
+            depth[start] = 0;
+            visited.push(start);
+            queue.push(start);
+
+            let mut head = 0;
+            while head < queue.len() {
+                let node = queue[head];
+                head += 1;
+                if 2 * (depth[node] + 1) > girth {
+                    break;
+                }
+                let (adjacent, offset) = self.neighbours(node);
+                for next in adjacent.iter().map(|x| x + offset) {
+                    if next == parent[node] {
+                        continue;
+                    }
+                    if depth[next] == usize::MAX {
+                        depth[next] = depth[node] + 1;
+                        parent[next] = node;
+                        visited.push(next);
+                        queue.push(next);
+                    } else {
+                        let length = depth[node] + depth[next] + 1;
+                        if length < girth {
+                            girth = length;
+                        }
+                    }
+                }
+            }
+        }
+
+        if girth == usize::MAX { None } else { Some(girth) }
+    }
+
+    /// Count the cycles of exactly `length` edges, which must be even and at least 4.
+    ///
+    /// Each cycle is split at its lowest numbered variable and the node opposite it into two
+    /// paths of `length/2` edges, so only those shorter paths need enumerating. This still
+    /// grows rapidly with `length`, but lengths up to 8 are quick for every code.
+    pub fn count_cycles(&self, length: usize) -> usize {
+        assert!(length >= 4 && length & 1 == 0, "length must be even and at least 4");
+        let half = length / 2;
+        let mut path = Vec::with_capacity(half + 1);
+        let mut paths = Vec::new();
+        let mut order = Vec::new();
+        let mut count = 0;
+
+        for start in 0..self.var_checks.len() {
+            // Find every path of `half` edges from `start` through higher numbered variables,
+            // stored as the end node followed by the interior nodes.
+            paths.clear();
+            path.push(start);
+            self.paths_from(start, half, &mut path, &mut paths);
+            path.pop();
+
+            // Any two paths to the same end node with disjoint interiors form a cycle
+            order.clear();
+            order.extend(0..paths.len() / half);
+            order.sort_unstable_by_key(|&i| paths[i * half]);
+            for (a, &i) in order.iter().enumerate() {
+                let p = &paths[i*half..(i+1)*half];
+                for &j in &order[a+1..] {
+                    let q = &paths[j*half..(j+1)*half];
+                    if q[0] != p[0] {
+                        break;
+                    }
+                    if p[1..].iter().all(|x| !q[1..].contains(x)) {
+                        count += 1;
+                    }
+                }
+            }
+        }
+
+        count
+    }
+
+    /// Append to `paths` every extension of `path` by `remaining` edges that does not revisit
+    /// a node or visit a variable numbered below `start`.
+    fn paths_from(&self, start: usize, remaining: usize, path: &mut Vec<usize>,
+                  paths: &mut Vec<usize>)
+    {
+        let (adjacent, offset) = self.neighbours(*path.last().unwrap());
+        for next in adjacent.iter().map(|x| x + offset) {
+            if path.contains(&next) || (next < self.var_checks.len() && next < start) {
+                continue;
+            }
+            if remaining == 1 {
+                paths.push(next);
+                paths.extend_from_slice(&path[1..]);
+            } else {
+                path.push(next);
+                self.paths_from(start, remaining - 1, path, paths);
+                path.pop();
+            }
+        }
+    }
+
+    /// Neighbours of a node, where variables are numbered first and then checks, as a list
+    /// and an offset to add to each entry.
+    fn neighbours(&self, node: usize) -> (&[usize], usize) {
+        let vars = self.var_checks.len();
+        if node < vars {
+            (&self.var_checks[node], vars)
+        } else {
+            (&self.check_vars[node - vars], 0)
+        }
+    }
+}
+
+fn histogram<'a, I: Iterator<Item=&'a Vec<usize>>>(lists: I) -> Vec<usize> {
+    let mut hist = Vec::new();
+    for list in lists {
+        if hist.len() <= list.len() {
+            hist.resize(list.len() + 1, 0);
+        }
+        hist[list.len()] += 1;
+    }
+    hist
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+
+    use ::codes::LDPCCode;
+    use super::TannerGraph;
+
+    struct Expected {
+        code: LDPCCode,
+        variable_degrees: &'static [usize],
+        check_degrees: &'static [usize],
+        punctured_degrees: &'static [usize],
+        girth: usize,
+        cycles: [usize; 3],
+    }
+
+    fn check(expected: &Expected) {
+        let graph = TannerGraph::new(expected.code);
+        assert_eq!(graph.variable_degrees(), expected.variable_degrees);
+        assert_eq!(graph.check_degrees(), expected.check_degrees);
+        assert_eq!(graph.punctured_degrees(), expected.punctured_degrees);
+        assert_eq!(graph.girth(), Some(expected.girth));
+        let cycles = [graph.count_cycles(4), graph.count_cycles(6), graph.count_cycles(8)];
+        assert_eq!(cycles, expected.cycles);
+
+        // Both degree distributions account for every edge
+        let edges = expected.code.paritycheck_sum() as usize;
+        let sum = |hist: Vec<usize>| hist.iter().enumerate().map(|(d, x)| d * x).sum::<usize>();
+        assert_eq!(sum(graph.variable_degrees()), edges);
+        assert_eq!(sum(graph.check_degrees()), edges);
+    }
+
+    #[test]
+    fn test_tc_codes() {
+        check(&Expected {
+            code: LDPCCode::TC128,
+            variable_degrees: &[0, 0, 0, 64, 0, 64],
+            check_degrees: &[0, 0, 0, 0, 0, 0, 0, 0, 64],
+            punctured_degrees: &[],
+            girth: 6,
+            cycles: [0, 2336, 32904],
+        });
+        check(&Expected {
+            code: LDPCCode::TC256,
+            variable_degrees: &[0, 0, 0, 128, 0, 128],
+            check_degrees: &[0, 0, 0, 0, 0, 0, 0, 0, 128],
+            punctured_degrees: &[],
+            girth: 6,
+            cycles: [0, 1856, 34592],
+        });
+        check(&Expected {
+            code: LDPCCode::TC512,
+            variable_degrees: &[0, 0, 0, 256, 0, 256],
+            check_degrees: &[0, 0, 0, 0, 0, 0, 0, 0, 256],
+            punctured_degrees: &[],
+            girth: 6,
+            cycles: [0, 896, 34400],
+        });
+    }
+
+    #[test]
+    fn test_tm_r45_codes() {
+        check(&Expected {
+            code: LDPCCode::TM1280,
+            variable_degrees: &[0, 128, 128, 256, 768, 0, 128],
+            check_degrees: &[0, 0, 0, 128, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 256],
+            punctured_degrees: &[0, 0, 0, 0, 0, 0, 128],
+            girth: 4,
+            cycles: [320, 16512, 591920],
+        });
+        check(&Expected {
+            code: LDPCCode::TM5120,
+            variable_degrees: &[0, 512, 512, 1024, 3072, 0, 512],
+            check_degrees: &[0, 0, 0, 512, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1024],
+            punctured_degrees: &[0, 0, 0, 0, 0, 0, 512],
+            girth: 4,
+            cycles: [128, 15872, 579072],
+        });
+    }
+
+    #[test]
+    fn test_tm_r23_codes() {
+        check(&Expected {
+            code: LDPCCode::TM1536,
+            variable_degrees: &[0, 256, 256, 512, 512, 0, 256],
+            check_degrees: &[0, 0, 0, 256, 0, 0, 0, 0, 0, 0, 512],
+            punctured_degrees: &[0, 0, 0, 0, 0, 0, 256],
+            girth: 4,
+            cycles: [128, 1920, 37312],
+        });
+        check(&Expected {
+            code: LDPCCode::TM6144,
+            variable_degrees: &[0, 1024, 1024, 2048, 2048, 0, 1024],
+            check_degrees: &[0, 0, 0, 1024, 0, 0, 0, 0, 0, 0, 2048],
+            punctured_degrees: &[0, 0, 0, 0, 0, 0, 1024],
+            girth: 6,
+            cycles: [0, 768, 35456],
+        });
+    }
+
+    #[test]
+    fn test_tm_r12_codes() {
+        check(&Expected {
+            code: LDPCCode::TM2048,
+            variable_degrees: &[0, 512, 512, 1024, 0, 0, 512],
+            check_degrees: &[0, 0, 0, 512, 0, 0, 1024],
+            punctured_degrees: &[0, 0, 0, 0, 0, 0, 512],
+            girth: 6,
+            cycles: [0, 128, 3520],
+        });
+        check(&Expected {
+            code: LDPCCode::TM8192,
+            variable_degrees: &[0, 2048, 2048, 4096, 0, 0, 2048],
+            check_degrees: &[0, 0, 0, 2048, 0, 0, 4096],
+            punctured_degrees: &[0, 0, 0, 0, 0, 0, 2048],
+            girth: 8,
+            cycles: [0, 0, 2048],
+        });
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! This module provides Attached Sync Marker (ASM) insertion and soft frame synchronisation.
+//!
+//! On transmit, each codeword is prefixed with a fixed marker so the receiver can find codeword
+//! boundaries in the continuous bitstream. On receive, the marker is located by correlating
+//! it against the received LLRs, which also reveals whether the demodulator has locked with
+//! inverted polarity (a common phase ambiguity with BPSK and QPSK).
+//!
+//! The correlation is normalised to the range -1 to +1 by the total LLR magnitude over the
+//! marker, so the same threshold works regardless of LLR scaling or type: +1 is a perfect
+//! match, -1 a perfect match with inverted polarity. A threshold around 0.7 is reasonable.
+//!
+//! ```
+//! # use labrador_ldpc::LDPCCode;
+//! use labrador_ldpc::asm::Asm;
+//!
+//! let code = LDPCCode::TC128;
+//! let txdata: Vec<u8> = (0..8).collect();
+//! let mut txcode = vec![0u8; code.n()/8];
+//! code.copy_encode(&txdata, &mut txcode);
+//!
+//! // Prefix the codeword with the 32-bit ASM
+//! let mut frame = vec![0u8; 4 + code.n()/8];
+//! Asm::Asm32.insert(&txcode, &mut frame);
+//!
+//! // Receive it with some junk beforehand and inverted polarity
+//! let mut rx = vec![0u8; 3];
+//! rx.extend_from_slice(&frame);
+//! let mut llrs: Vec<i8> = (0..rx.len()*8)
+//!     .map(|i| if rx[i/8] >> (7 - (i%8)) & 1 == 1 { 1 } else { -1 }).collect();
+//!
+//! // Find the codeword, which is returned with its polarity corrected
+//! let (sync, rxllrs) = Asm::Asm32.next_codeword(&mut llrs, code.n(), 0.8).unwrap();
+//! assert_eq!(sync.offset, 24);
+//! assert!(sync.inverted);
+//!
+//! let mut working = vec![0i8; code.decode_ms_working_len()];
+//! let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+//! let mut rxdata = vec![0u8; code.output_len()];
+//! code.decode_ms(rxllrs, &mut rxdata, &mut working, &mut working_u8, 20);
+//! assert_eq!(&rxdata[..8], &txdata[..]);
+//! ```
+
+use ::decoder::{DecodeFrom, FloatConvert, saturating_neg};
+
+/// Available CCSDS attached sync markers.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub enum Asm {
+    /// 32-bit marker 0x1ACFFC1D, used with the TM LDPC codes.
+    Asm32,
+
+    /// 64-bit marker 0x034776C7272895B0.
+    ///
+    /// This is also the start sequence of LDPC-coded telecommand CLTUs.
+    Asm64,
+}
+
+static ASM32: [u8; 4] = [0x1A, 0xCF, 0xFC, 0x1D];
+static ASM64: [u8; 8] = [0x03, 0x47, 0x76, 0xC7, 0x27, 0x28, 0x95, 0xB0];
+
+/// Result of a successful search for a sync marker.
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub struct SyncMatch {
+    /// Index of the first bit of the marker in the searched LLRs.
+    pub offset: usize,
+
+    /// True if the marker was found with inverted polarity.
+    pub inverted: bool,
+
+    /// Normalised correlation at `offset`, between -1 and +1.
+    pub correlation: f32,
+}
+
+impl Asm {
+    /// Get the marker bytes, MSB first.
+    pub fn bytes(&self) -> &'static [u8] {
+        match *self {
+            Asm::Asm32 => &ASM32,
+            Asm::Asm64 => &ASM64,
+        }
+    }
+
+    /// Get the length of the marker in bits.
+    pub fn bits(&self) -> usize {
+        self.bytes().len() * 8
+    }
+
+    /// Write the marker followed by `codeword` into `output`.
+    ///
+    /// `output` must be exactly `self.bits()/8 + codeword.len()` bytes long.
+    pub fn insert(&self, codeword: &[u8], output: &mut [u8]) {
+        let asm = self.bytes();
+        assert_eq!(output.len(), asm.len() + codeword.len(), "output.len() incorrect");
+        output[..asm.len()].copy_from_slice(asm);
+        output[asm.len()..].copy_from_slice(codeword);
+    }
+
+    /// Compute the normalised soft correlation of the marker against the start of `llrs`.
+    ///
+    /// `llrs` must be at least `self.bits()` long. Returns a value between -1 and +1, where
+    /// positive values indicate a match and negative values an inverted match.
+    pub fn correlate<T: FloatConvert>(&self, llrs: &[T]) -> f32 {
+        assert!(llrs.len() >= self.bits(), "llrs shorter than ASM");
+        let mut corr = 0.0;
+        let mut total = 0.0;
+        for (i, llr) in llrs[..self.bits()].iter().enumerate() {
+            // Convert before taking the magnitude, since `abs` of an integer MIN overflows
+            let llr = llr.to_f32();
+            if self.bytes()[i/8] >> (7 - (i%8)) & 1 == 1 {
+                corr -= llr;
+            } else {
+                corr += llr;
+            }
+            total += DecodeFrom::abs(&llr);
+        }
+        if total == 0.0 { 0.0 } else { corr / total }
+    }
+
+    /// Search `llrs` for the marker followed by `codeword_len` bits.
+    ///
+    /// Returns the first location where the magnitude of the normalised correlation reaches
+    /// `threshold` (refined to the strongest peak within one marker length after it), or
+    /// `None` if no marker with a complete codeword following it was found.
+    pub fn find<T: FloatConvert>(&self, llrs: &[T], codeword_len: usize, threshold: f32)
+        -> Option<SyncMatch>
+    {
+        let asm_len = self.bits();
+        if llrs.len() < asm_len + codeword_len {
+            return None;
+        }
+        let last = llrs.len() - asm_len - codeword_len;
+
+        let mut best: Option<SyncMatch> = None;
+        for offset in 0..(last + 1) {
+            if let Some(b) = best {
+                if offset >= b.offset + asm_len {
+                    break;
+                }
+            }
+            let correlation = self.correlate(&llrs[offset..]);
+            let strength = DecodeFrom::abs(&correlation);
+            if strength >= threshold {
+                let better = match best {
+                    Some(b) => strength > DecodeFrom::abs(&b.correlation),
+                    None    => true,
+                };
+                if better {
+                    best = Some(SyncMatch { offset, inverted: correlation < 0.0, correlation });
+                }
+            }
+        }
+        best
+    }
+
+    /// Find the next codeword of `codeword_len` bits in `llrs`.
+    ///
+    /// Searches for the marker as in `find`, and if found returns the match along with the
+    /// slice of `llrs` holding the codeword which immediately follows it. If the marker was
+    /// inverted, the codeword LLRs are negated in place first, so the returned slice is ready
+    /// to pass to `decode_ms`.
+    ///
+    /// To continue through a stream, search again from `offset + self.bits() + codeword_len`.
+    pub fn next_codeword<'a, T: FloatConvert>(&self, llrs: &'a mut [T], codeword_len: usize,
+                                            threshold: f32)
+        -> Option<(SyncMatch, &'a mut [T])>
+    {
+        let sync = self.find(llrs, codeword_len, threshold)?;
+        let start = sync.offset + self.bits();
+        let codeword = &mut llrs[start..start + codeword_len];
+        if sync.inverted {
+            for llr in codeword.iter_mut() {
+                *llr = saturating_neg(*llr);
+            }
+        }
+        Some((sync, codeword))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+
+    use super::Asm;
+
+    fn to_llrs(bytes: &[u8]) -> Vec<f32> {
+        (0..bytes.len()*8).map(|i| if bytes[i/8] >> (7 - (i%8)) & 1 == 1 { -1.0 } else { 1.0 })
+                          .collect()
+    }
+
+    #[test]
+    fn test_correlate() {
+        for asm in &[Asm::Asm32, Asm::Asm64] {
+            let llrs = to_llrs(asm.bytes());
+            assert_eq!(asm.correlate(&llrs), 1.0);
+            let inv: Vec<f32> = llrs.iter().map(|x| -x).collect();
+            assert_eq!(asm.correlate(&inv), -1.0);
+            assert_eq!(asm.correlate(&vec![0.0f32; asm.bits()]), 0.0);
+            let ones = asm.correlate(&vec![1.0f32; asm.bits()]);
+            assert_eq!(asm.correlate(&vec![i8::MIN; asm.bits()]), -ones);
+        }
+    }
+
+    #[test]
+    fn test_find_stream() {
+        // Build a stream of junk, then two 16-byte "codewords" each with an ASM
+        let mut stream = vec![0x55u8, 0x12, 0x34];
+        for cw in 0..2 {
+            let codeword: Vec<u8> = (0..16).map(|i| (i * 17 + cw) as u8).collect();
+            let mut frame = vec![0u8; 8 + 16];
+            Asm::Asm64.insert(&codeword, &mut frame);
+            stream.extend_from_slice(&frame);
+        }
+        let mut llrs: Vec<i16> = to_llrs(&stream).iter().map(|x| (*x * 10.0) as i16).collect();
+
+        // Corrupt one bit of the first marker
+        llrs[24 + 5] = -llrs[24 + 5];
+
+        let sync = Asm::Asm64.find(&llrs, 128, 0.8).unwrap();
+        assert_eq!(sync.offset, 24);
+        assert!(!sync.inverted);
+
+        let next = sync.offset + 64 + 128;
+        let sync = Asm::Asm64.find(&llrs[next..], 128, 0.8).unwrap();
+        assert_eq!(sync.offset, 0);
+
+        // Not enough room after the second marker for a longer codeword
+        assert!(Asm::Asm64.find(&llrs[next..], 256, 0.8).is_none());
+    }
+
+    #[test]
+    fn test_next_codeword_inverted() {
+        let codeword: Vec<u8> = (0..8).map(|i| i * 29).collect();
+        let mut frame = vec![0u8; 4 + 8];
+        Asm::Asm32.insert(&codeword, &mut frame);
+        let expected: Vec<i8> = to_llrs(&codeword).iter().map(|x| (*x * 100.0) as i8).collect();
+
+        // Invert the whole frame, with one codeword LLR at i8::MIN, whose negation overflows
+        let mut llrs: Vec<i8> = to_llrs(&frame).iter().map(|x| (*x * -100.0) as i8).collect();
+        assert_eq!(llrs[32], -100);
+        llrs[32] = i8::MIN;
+
+        let (sync, llrs) = Asm::Asm32.next_codeword(&mut llrs, 64, 0.8).unwrap();
+        assert_eq!(sync.offset, 0);
+        assert!(sync.inverted);
+        assert_eq!(llrs[0], i8::MAX);
+        assert_eq!(&llrs[1..], &expected[1..]);
+    }
+}
@@ -0,0 +1,236 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! This module provides iterative demapping and decoding (BICM-ID) for higher-order
+//! modulations.
+//!
+//! With bit-interleaved coded modulation, the demapper normally computes LLRs once and hands
+//! them to the decoder. When the constellation labelling is not perfectly Gray (as for the
+//! APSK constellations), the bits sharing a symbol are not independent, and feeding the
+//! decoder's extrinsic information back to the demapper as prior knowledge of the other bits
+//! in each symbol can improve the LLRs.
+//!
+//! `decode_bicm` alternates between `Constellation::demap_with_priors` and
+//! `decode_ms_extrinsic` for up to `outer_iters` outer iterations, each running the min-sum
+//! decoder for up to `inner_iters` iterations, stopping as soon as a codeword is found. An
+//! optional `BlockInterleaver` sits between the two. The decoder starts afresh from the
+//! improved LLRs in each outer iteration, so `inner_iters` should still be large enough for
+//! the decoder to make good progress on its own.
+//!
+//! ```
+//! # use labrador_ldpc::LDPCCode;
+//! use labrador_ldpc::bicm::BicmParams;
+//! use labrador_ldpc::demapper::{Constellation, Demapping};
+//!
+//! let code = LDPCCode::TC512;
+//! let params = BicmParams {
+//!     constellation: Constellation::APSK16 { gamma: 3.15 },
+//!     demapping: Demapping::MaxLog,
+//!     interleaver: None,
+//!     outer_iters: 4,
+//!     inner_iters: 10,
+//!     scale: 1.0,
+//! };
+//!
+//! let txdata: Vec<u8> = (0..code.k()/8).map(|i| i as u8).collect();
+//! let mut txcode = vec![0u8; code.n()/8];
+//! code.copy_encode(&txdata, &mut txcode);
+//! let mut symbols = vec![(0.0, 0.0); params.constellation.symbols_for_bits(code.n())];
+//! params.constellation.map(&txcode, code.n(), &mut symbols);
+//!
+//! let mut working = vec![0f32; code.decode_ms_working_len()];
+//! let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+//! let mut bicm_working = vec![0f32; code.decode_bicm_working_len()];
+//! let mut rxdata = vec![0u8; code.output_len()];
+//! let result = code.decode_bicm(&params, &symbols, 0.05, &mut rxdata, &mut working,
+//!                               &mut working_u8, &mut bicm_working);
+//! assert!(result.success);
+//! assert_eq!(&rxdata[..code.k()/8], &txdata[..]);
+//! ```
+
+use ::codes::LDPCCode;
+use ::decoder::FloatConvert;
+use ::demapper::{Constellation, Demapping};
+use ::interleaver::BlockInterleaver;
+
+/// Parameters for iterative demapping and decoding.
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub struct BicmParams {
+    /// Constellation the codeword was mapped with.
+    pub constellation: Constellation,
+
+    /// Demapping method.
+    pub demapping: Demapping,
+
+    /// Interleaver applied between the encoder and the mapper, if any.
+    pub interleaver: Option<BlockInterleaver>,
+
+    /// Maximum number of demapper/decoder iterations.
+    pub outer_iters: usize,
+
+    /// Maximum number of min-sum iterations in each outer iteration.
+    pub inner_iters: usize,
+
+    /// Scale applied to LLRs before conversion to `T`, as for `Constellation::demap`.
+    pub scale: f32,
+}
+
+/// Result of iterative demapping and decoding.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub struct BicmResult {
+    /// True if a codeword was found.
+    pub success: bool,
+
+    /// Number of outer iterations run.
+    pub outer_iters: usize,
+
+    /// Total number of min-sum iterations run over all outer iterations.
+    pub inner_iters: usize,
+}
+
+impl LDPCCode {
+    /// Get the length of [T] required for the `bicm_working` area of `decode_bicm`.
+    ///
+    /// Equal to 3 * n.
+    pub fn decode_bicm_working_len(&self) -> usize {
+        3 * self.n()
+    }
+
+    /// Decode received `samples` using iterative demapping and decoding.
+    ///
+    /// * `params` gives the constellation, interleaver and iteration counts
+    /// * `samples` are the received symbols, `params.constellation.symbols_for_bits(n)` long
+    /// * `noise_var` is the complex noise variance N0, as for `Constellation::demap`
+    /// * `output`, `working` and `working_u8` are as for `decode_ms`
+    /// * `bicm_working` must have `decode_bicm_working_len()` elements
+    ///
+    /// After the first outer iteration the result is identical to demapping and then calling
+    /// `decode_ms`. `output` holds the final hard decisions whether or not decoding succeeded.
+    #[allow(clippy::too_many_arguments)]
+    pub fn decode_bicm<T: FloatConvert>(&self, params: &BicmParams, samples: &[(f32, f32)],
+                                      noise_var: f32, output: &mut [u8], working: &mut [T],
+                                      working_u8: &mut [u8], bicm_working: &mut [T])
+        -> BicmResult
+    {
+        let n = self.n();
+        assert_eq!(bicm_working.len(), self.decode_bicm_working_len(),
+                   "bicm_working.len() incorrect");
+        assert!(params.outer_iters > 0, "outer_iters must be at least 1");
+        if let Some(il) = params.interleaver {
+            assert_eq!(il.n(), n, "interleaver not sized for this code");
+        }
+
+        // Priors and demapper output are in transmitted order, decoder input in code order.
+        let (priors, bicm_working) = bicm_working.split_at_mut(n);
+        let (channel, decoder_llrs) = bicm_working.split_at_mut(n);
+        for x in priors.iter_mut() { *x = T::zero() }
+
+        let mut result = BicmResult { success: false, outer_iters: 0, inner_iters: 0 };
+        while result.outer_iters < params.outer_iters {
+            params.constellation.demap_with_priors(params.demapping, samples, noise_var,
+                                                   params.scale, priors, channel);
+            match params.interleaver {
+                Some(il) => il.deinterleave_llrs(channel, decoder_llrs),
+                None => decoder_llrs.copy_from_slice(channel),
+            }
+
+            // The demapper output is no longer needed, so hold the decoder extrinsic there
+            let extrinsic = &mut channel[..];
+            let (success, iters) = self.decode_ms_extrinsic(decoder_llrs, output, working,
+                                                            working_u8, params.inner_iters,
+                                                            extrinsic);
+            result.outer_iters += 1;
+            result.inner_iters += iters;
+            if success {
+                result.success = true;
+                break;
+            }
+
+            match params.interleaver {
+                Some(il) => il.interleave_llrs(extrinsic, priors),
+                None => priors.copy_from_slice(extrinsic),
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+
+    use ::codes::LDPCCode;
+    use ::demapper::{Constellation, Demapping};
+    use ::interleaver::BlockInterleaver;
+    use ::sim::Rng;
+    use super::BicmParams;
+
+    #[test]
+    fn test_bicm() {
+        let code = LDPCCode::TM2048;
+        let constellation = Constellation::APSK32 { gamma1: 2.84, gamma2: 5.27 };
+        let mut params = BicmParams {
+            constellation,
+            demapping: Demapping::MaxLog,
+            interleaver: Some(BlockInterleaver::for_code(code, 5)),
+            outer_iters: 1,
+            inner_iters: 8,
+            scale: 1.0,
+        };
+        let il = params.interleaver.unwrap();
+        let n0 = 0.06;
+        let mut rng = Rng::new(17);
+
+        let mut working = vec![0f32; code.decode_ms_working_len()];
+        let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+        let mut bicm_working = vec![0f32; code.decode_bicm_working_len()];
+        let mut output = vec![0u8; code.output_len()];
+        let mut successes = [0, 0];
+
+        for _ in 0..20 {
+            let mut txdata = vec![0u8; code.k()/8];
+            rng.fill_bytes(&mut txdata);
+            let mut txcode = vec![0u8; code.n()/8];
+            code.copy_encode(&txdata, &mut txcode);
+            let mut txbits = vec![0u8; code.n()/8];
+            il.interleave(&txcode, &mut txbits);
+            let mut symbols = vec![(0.0, 0.0); constellation.symbols_for_bits(code.n())];
+            constellation.map(&txbits, code.n(), &mut symbols);
+            for s in &mut symbols {
+                s.0 += ((n0 / 2.0f64).sqrt() * rng.gaussian()) as f32;
+                s.1 += ((n0 / 2.0f64).sqrt() * rng.gaussian()) as f32;
+            }
+
+            // A single outer iteration is the same as demapping then decoding
+            params.outer_iters = 1;
+            let single = code.decode_bicm(&params, &symbols, n0 as f32, &mut output,
+                                          &mut working, &mut working_u8, &mut bicm_working);
+            let mut llrs = vec![0f32; code.n()];
+            let mut rxllrs = vec![0f32; code.n()];
+            constellation.demap(Demapping::MaxLog, &symbols, n0 as f32, 1.0, &mut rxllrs);
+            il.deinterleave_llrs(&rxllrs, &mut llrs);
+            let mut plain = vec![0u8; code.output_len()];
+            let (success, iters) = code.decode_ms(&llrs, &mut plain, &mut working,
+                                                  &mut working_u8, 8);
+            assert_eq!((single.success, single.inner_iters), (success, iters));
+            assert_eq!(plain, output);
+
+            // Feeding back extrinsic information recovers frames a single pass could not
+            params.outer_iters = 6;
+            let multi = code.decode_bicm(&params, &symbols, n0 as f32, &mut output,
+                                         &mut working, &mut working_u8, &mut bicm_working);
+            if multi.success {
+                assert_eq!(&output[..code.k()/8], &txdata[..]);
+            }
+            if single.success {
+                assert!(multi.success);
+                assert_eq!(multi.outer_iters, 1);
+            }
+            successes[0] += single.success as usize;
+            successes[1] += multi.success as usize;
+        }
+
+        assert!(successes[1] > successes[0]);
+    }
+}
@@ -0,0 +1,442 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! Command-line BER/FER curve generator.
+//!
+//! Sweeps Eb/N0 over an AWGN channel for the selected codes and decoders, running each point
+//! until a target number of frame errors (or a frame limit) is reached, and writes bit error
+//! rate, frame error rate and average iteration count as CSV or JSON.
+//!
+//! Requires the `std` feature: `cargo run --release --features std --bin labrador-sim -- -h`
+
+extern crate labrador_ldpc;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Write};
+use std::process;
+
+use labrador_ldpc::LDPCCode;
+use labrador_ldpc::decoder::FloatConvert;
+use labrador_ldpc::sim::{AwgnChannel, Modulation};
+
+const USAGE: &str = "\
+Usage: labrador-sim [options]
+
+Options:
+    --codes LIST        Comma-separated codes, or 'all' (default TC128)
+                        TC128, TC256, TC512, TM1280, TM1536, TM2048, TM5120, TM6144, TM8192
+    --decoders LIST     Comma-separated decoders (default ms-f32)
+                        bf, ms-i8, ms-i16, ms-i32, ms-f32, ms-f64
+    --ebn0 START:STOP:STEP
+                        Eb/N0 sweep in dB, inclusive (default 0:4:0.5)
+    --modulation MOD    bpsk or qpsk (default bpsk)
+    --target-errors N   Frame errors to collect at each point (default 100)
+    --max-frames N      Maximum frames to run at each point (default 100000)
+    --max-iters N       Maximum decoder iterations (default 50)
+    --scale X           Scale applied to LLRs before conversion to the decoder type (default 1)
+    --seed N            Random seed (default 0)
+    --format FMT        csv or json (default csv)
+    --output FILE       Write results to FILE instead of stdout
+    -h, --help          Show this help
+";
+
+static CODES: [LDPCCode; 9] = [
+    LDPCCode::TC128, LDPCCode::TC256, LDPCCode::TC512,
+    LDPCCode::TM1280, LDPCCode::TM1536, LDPCCode::TM2048,
+    LDPCCode::TM5120, LDPCCode::TM6144, LDPCCode::TM8192,
+];
+
+#[derive(Copy,Clone,Debug,PartialEq)]
+enum Decoder {
+    BF,
+    MsI8,
+    MsI16,
+    MsI32,
+    MsF32,
+    MsF64,
+}
+
+impl Decoder {
+    fn name(&self) -> &'static str {
+        match *self {
+            Decoder::BF     => "bf",
+            Decoder::MsI8   => "ms-i8",
+            Decoder::MsI16  => "ms-i16",
+            Decoder::MsI32  => "ms-i32",
+            Decoder::MsF32  => "ms-f32",
+            Decoder::MsF64  => "ms-f64",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Decoder> {
+        [Decoder::BF, Decoder::MsI8, Decoder::MsI16, Decoder::MsI32, Decoder::MsF32,
+         Decoder::MsF64].iter().cloned().find(|d| d.name() == name)
+    }
+}
+
+#[derive(Copy,Clone,Debug,PartialEq)]
+enum Format {
+    Csv,
+    Json,
+}
+
+struct Config {
+    codes: Vec<LDPCCode>,
+    decoders: Vec<Decoder>,
+    ebn0: Vec<f64>,
+    modulation: Modulation,
+    target_errors: usize,
+    max_frames: usize,
+    max_iters: usize,
+    scale: f32,
+    seed: u64,
+    format: Format,
+    output: Option<String>,
+}
+
+/// Results for one code, decoder and Eb/N0.
+struct Point {
+    code: LDPCCode,
+    decoder: Decoder,
+    ebn0: f64,
+    frames: usize,
+    bit_errors: usize,
+    frame_errors: usize,
+    iters: usize,
+}
+
+impl Point {
+    fn ber(&self) -> f64 {
+        self.bit_errors as f64 / (self.frames * self.code.k()) as f64
+    }
+
+    fn fer(&self) -> f64 {
+        self.frame_errors as f64 / self.frames as f64
+    }
+
+    fn avg_iters(&self) -> f64 {
+        self.iters as f64 / self.frames as f64
+    }
+}
+
+fn parse_code(name: &str) -> Result<LDPCCode, String> {
+    CODES.iter().cloned().find(|c| format!("{:?}", c).eq_ignore_ascii_case(name))
+         .ok_or_else(|| format!("unknown code '{}'", name))
+}
+
+fn parse_number<T: std::str::FromStr>(opt: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value '{}' for {}", value, opt))
+}
+
+/// Count the decimal places written in `text`, or None if it uses an exponent.
+fn decimal_places(text: &str) -> Option<usize> {
+    if text.contains(['e', 'E']) {
+        return None;
+    }
+    Some(text.find('.').map(|i| text.len() - i - 1).unwrap_or(0))
+}
+
+fn parse_ebn0(value: &str) -> Result<Vec<f64>, String> {
+    let texts: Vec<&str> = value.split(':').collect();
+    let parts: Vec<f64> = match texts.len() {
+        1 => {
+            let x = parse_number("--ebn0", texts[0])?;
+            vec![x, x, 1.0]
+        },
+        3 => texts.iter().map(|p| parse_number("--ebn0", p)).collect::<Result<_, _>>()?,
+        _ => return Err("--ebn0 must be START:STOP:STEP or a single value".to_string()),
+    };
+    let (start, stop, step) = (parts[0], parts[1], parts[2]);
+    if step <= 0.0 || stop < start {
+        return Err("--ebn0 must have STOP >= START and STEP > 0".to_string());
+    }
+    // Allow for rounding error in the step so the end point is included
+    let points = ((stop - start) / step + 1e-9).floor() as usize + 1;
+    let mut ebn0: Vec<f64> = (0..points).map(|i| start + i as f64 * step).collect();
+
+    // Every point has at most as many decimal places as START and STEP, so round to that
+    // to give 0.9 rather than 0.8999999999999999
+    let start_places = decimal_places(texts[0]);
+    let step_places = decimal_places(texts[texts.len() - 1]);
+    if let (Some(a), Some(b)) = (start_places, step_places) {
+        let factor = 10f64.powi(a.max(b) as i32);
+        for x in &mut ebn0 {
+            *x = (*x * factor).round() / factor;
+        }
+    }
+    Ok(ebn0)
+}
+
+fn parse_args(args: &[String]) -> Result<Config, String> {
+    let mut config = Config {
+        codes: vec![LDPCCode::TC128],
+        decoders: vec![Decoder::MsF32],
+        ebn0: parse_ebn0("0:4:0.5")?,
+        modulation: Modulation::BPSK,
+        target_errors: 100,
+        max_frames: 100_000,
+        max_iters: 50,
+        scale: 1.0,
+        seed: 0,
+        format: Format::Csv,
+        output: None,
+    };
+
+    let mut args = args.iter();
+    while let Some(opt) = args.next() {
+        if opt == "-h" || opt == "--help" {
+            print!("{}", USAGE);
+            process::exit(0);
+        }
+        let value = args.next().ok_or_else(|| format!("missing value for {}", opt))?;
+        match opt.as_str() {
+            "--codes" => {
+                config.codes = if value == "all" {
+                    CODES.to_vec()
+                } else {
+                    value.split(',').map(parse_code).collect::<Result<_, _>>()?
+                };
+            },
+            "--decoders" => {
+                config.decoders = value.split(',').map(|d| {
+                    Decoder::from_name(d).ok_or_else(|| format!("unknown decoder '{}'", d))
+                }).collect::<Result<_, _>>()?;
+            },
+            "--ebn0" => config.ebn0 = parse_ebn0(value)?,
+            "--modulation" => {
+                config.modulation = match value.as_str() {
+                    "bpsk" => Modulation::BPSK,
+                    "qpsk" => Modulation::QPSK,
+                    _ => return Err(format!("unknown modulation '{}'", value)),
+                };
+            },
+            "--target-errors" => config.target_errors = parse_number(opt, value)?,
+            "--max-frames" => config.max_frames = parse_number(opt, value)?,
+            "--max-iters" => config.max_iters = parse_number(opt, value)?,
+            "--scale" => config.scale = parse_number(opt, value)?,
+            "--seed" => config.seed = parse_number(opt, value)?,
+            "--format" => {
+                config.format = match value.as_str() {
+                    "csv" => Format::Csv,
+                    "json" => Format::Json,
+                    _ => return Err(format!("unknown format '{}'", value)),
+                };
+            },
+            "--output" => config.output = Some(value.clone()),
+            _ => return Err(format!("unknown option '{}'", opt)),
+        }
+    }
+
+    if config.codes.is_empty() || config.decoders.is_empty() {
+        return Err("at least one code and decoder must be given".to_string());
+    }
+    if config.target_errors == 0 || config.max_frames == 0 {
+        return Err("--target-errors and --max-frames must be at least 1".to_string());
+    }
+
+    Ok(config)
+}
+
+/// Count the bits which differ between `a` and `b`.
+fn bit_errors(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b.iter()).map(|(x, y)| (x ^ y).count_ones() as usize).sum()
+}
+
+/// Run frames through the channel until enough frame errors are seen.
+///
+/// `decode` is given the received LLRs and the output buffer, and returns the number of
+/// iterations it ran for.
+fn run_point<T, F>(config: &Config, code: LDPCCode, decoder: Decoder, ebn0: f64, mut decode: F)
+    -> Point
+    where T: FloatConvert, F: FnMut(&[T], &mut [u8]) -> usize
+{
+    // Use the same seed for every decoder so they see identical noise
+    let seed = config.seed ^ ((ebn0 * 1000.0) as i64 as u64).wrapping_mul(0x9E37_79B9);
+    let mut channel = AwgnChannel::new(code, config.modulation, ebn0, seed);
+
+    let k = code.k() / 8;
+    let mut txdata = vec![0u8; k];
+    let mut txcode = vec![0u8; code.n()/8];
+    let mut llrs = vec![T::zero(); code.n()];
+    let mut output = vec![0u8; code.output_len()];
+
+    let mut point = Point { code, decoder, ebn0, frames: 0, bit_errors: 0, frame_errors: 0,
+                            iters: 0 };
+    while point.frame_errors < config.target_errors && point.frames < config.max_frames {
+        channel.rng().fill_bytes(&mut txdata);
+        code.copy_encode(&txdata, &mut txcode);
+        channel.transmit(&txcode, &mut llrs, config.scale);
+
+        point.iters += decode(&llrs, &mut output);
+        let errors = bit_errors(&txdata, &output[..k]);
+        point.frames += 1;
+        point.bit_errors += errors;
+        if errors > 0 {
+            point.frame_errors += 1;
+        }
+    }
+    point
+}
+
+fn run_ms<T: FloatConvert>(config: &Config, code: LDPCCode, decoder: Decoder, ebn0: f64)
+    -> Point
+{
+    let mut working = vec![T::zero(); code.decode_ms_working_len()];
+    let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+    run_point(config, code, decoder, ebn0, |llrs: &[T], output: &mut [u8]| {
+        code.decode_ms(llrs, output, &mut working, &mut working_u8, config.max_iters).1
+    })
+}
+
+fn run_bf(config: &Config, code: LDPCCode, ebn0: f64) -> Point {
+    let mut working = vec![0u8; code.decode_bf_working_len()];
+    let mut input = vec![0u8; code.n()/8];
+    run_point(config, code, Decoder::BF, ebn0, |llrs: &[f32], output: &mut [u8]| {
+        for x in &mut input[..] { *x = 0 }
+        for (i, llr) in llrs.iter().enumerate() {
+            if *llr <= 0.0 {
+                input[i/8] |= 1 << (7 - (i%8));
+            }
+        }
+        code.decode_bf(&input, output, &mut working, config.max_iters).1
+    })
+}
+
+fn simulate(config: &Config, code: LDPCCode, decoder: Decoder, ebn0: f64) -> Point {
+    match decoder {
+        Decoder::BF     => run_bf(config, code, ebn0),
+        Decoder::MsI8   => run_ms::<i8>(config, code, decoder, ebn0),
+        Decoder::MsI16  => run_ms::<i16>(config, code, decoder, ebn0),
+        Decoder::MsI32  => run_ms::<i32>(config, code, decoder, ebn0),
+        Decoder::MsF32  => run_ms::<f32>(config, code, decoder, ebn0),
+        Decoder::MsF64  => run_ms::<f64>(config, code, decoder, ebn0),
+    }
+}
+
+fn write_csv(out: &mut dyn Write, points: &[Point]) -> io::Result<()> {
+    writeln!(out, "code,decoder,ebn0_db,frames,bit_errors,frame_errors,ber,fer,avg_iters")?;
+    for p in points {
+        writeln!(out, "{:?},{},{},{},{},{},{:e},{:e},{:.3}", p.code, p.decoder.name(), p.ebn0,
+                 p.frames, p.bit_errors, p.frame_errors, p.ber(), p.fer(), p.avg_iters())?;
+    }
+    Ok(())
+}
+
+fn write_json(out: &mut dyn Write, points: &[Point]) -> io::Result<()> {
+    writeln!(out, "[")?;
+    for (i, p) in points.iter().enumerate() {
+        let sep = if i + 1 < points.len() { "," } else { "" };
+        writeln!(out, "  {{\"code\": \"{:?}\", \"decoder\": \"{}\", \"ebn0_db\": {}, \
+                       \"frames\": {}, \"bit_errors\": {}, \"frame_errors\": {}, \
+                       \"ber\": {:e}, \"fer\": {:e}, \"avg_iters\": {:.3}}}{}",
+                 p.code, p.decoder.name(), p.ebn0, p.frames, p.bit_errors, p.frame_errors,
+                 p.ber(), p.fer(), p.avg_iters(), sep)?;
+    }
+    writeln!(out, "]")
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let config = match parse_args(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("labrador-sim: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        },
+    };
+
+    let mut points = Vec::new();
+    for &code in &config.codes {
+        for &decoder in &config.decoders {
+            for &ebn0 in &config.ebn0 {
+                let point = simulate(&config, code, decoder, ebn0);
+                eprintln!("{:?} {} {:.2}dB: {} frames, BER {:e}, FER {:e}", code,
+                          decoder.name(), ebn0, point.frames, point.ber(), point.fer());
+                points.push(point);
+            }
+        }
+    }
+
+    let result = match config.output {
+        Some(ref path) => File::create(path).and_then(|mut f| match config.format {
+            Format::Csv => write_csv(&mut f, &points),
+            Format::Json => write_json(&mut f, &points),
+        }),
+        None => {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            match config.format {
+                Format::Csv => write_csv(&mut out, &points),
+                Format::Json => write_json(&mut out, &points),
+            }
+        },
+    };
+    if let Err(e) = result {
+        eprintln!("labrador-sim: error writing results: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn point() -> Point {
+        Point { code: LDPCCode::TC128, decoder: Decoder::MsF32, ebn0: 1.5, frames: 10,
+                bit_errors: 32, frame_errors: 2, iters: 125 }
+    }
+
+    #[test]
+    fn test_parse_ebn0() {
+        assert_eq!(parse_ebn0("2.5").unwrap(), vec![2.5]);
+        assert_eq!(parse_ebn0("0:2:0.5").unwrap(), vec![0.0, 0.5, 1.0, 1.5, 2.0]);
+        assert_eq!(parse_ebn0("0.5:0.9:0.1").unwrap(), vec![0.5, 0.6, 0.7, 0.8, 0.9]);
+        assert_eq!(parse_ebn0("0.25:1:0.5").unwrap(), vec![0.25, 0.75]);
+        assert_eq!(parse_ebn0("1:1:1e-1").unwrap(), vec![1.0]);
+        assert!(parse_ebn0("0:2:0").is_err());
+        assert!(parse_ebn0("0:2:-0.5").is_err());
+        assert!(parse_ebn0("2:0:0.5").is_err());
+        assert!(parse_ebn0("0:2").is_err());
+        assert!(parse_ebn0("0:x:1").is_err());
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let config = parse_args(&args(&["--codes", "tc256,TM1280", "--decoders", "bf,ms-i8",
+                                        "--ebn0", "1:2:1", "--format", "json"])).unwrap();
+        assert_eq!(config.codes, vec![LDPCCode::TC256, LDPCCode::TM1280]);
+        assert_eq!(config.decoders, vec![Decoder::BF, Decoder::MsI8]);
+        assert_eq!(config.ebn0, vec![1.0, 2.0]);
+        assert_eq!(config.format, Format::Json);
+        assert_eq!(parse_args(&args(&["--codes", "all"])).unwrap().codes, CODES.to_vec());
+
+        for bad in &[&["--codes"][..], &["--codes", "TC64"], &["--decoders", "ms-u8"],
+                     &["--modulation", "8psk"], &["--format", "xml"], &["--max-iters", "-1"],
+                     &["--scale", "big"], &["--target-errors", "0"], &["--max-frames", "0"],
+                     &["--ebn0", "3:1:1"], &["--bogus", "1"]] {
+            assert!(parse_args(&args(bad)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_write() {
+        let mut out = Vec::new();
+        write_csv(&mut out, &[point()]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "code,decoder,ebn0_db,frames,bit_errors,frame_errors,ber,fer,avg_iters\n\
+                    TC128,ms-f32,1.5,10,32,2,5e-2,2e-1,12.500\n");
+
+        let mut out = Vec::new();
+        write_json(&mut out, &[point(), point()]).unwrap();
+        let line = "{\"code\": \"TC128\", \"decoder\": \"ms-f32\", \"ebn0_db\": 1.5, \
+                    \"frames\": 10, \"bit_errors\": 32, \"frame_errors\": 2, \
+                    \"ber\": 5e-2, \"fer\": 2e-1, \"avg_iters\": 12.500}";
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   format!("[\n  {},\n  {}\n]\n", line, line));
+    }
+}
@@ -0,0 +1,215 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! This module provides bit-slice views onto byte buffers, so that codewords need not be
+//! byte-aligned or a whole number of bytes inside a larger frame.
+//!
+//! A `BitSlice` (or the mutable `BitSliceMut`) selects `len` bits starting at bit `offset`
+//! inside an `&[u8]`, with bits within each byte numbered either MSB-first (the convention used
+//! everywhere else in this crate) or LSB-first.
+//!
+//! Please refer to the `encode_bits`, `copy_encode_bits`, `decode_bf_bits`, `hard_bits_to_llrs`
+//! and `llrs_to_hard_bits` methods on [`LDPCCode`](../codes/enum.LDPCCode.html) for use with
+//! the encoders and decoders.
+
+/// Order in which bits are numbered inside each byte.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub enum BitOrder {
+    /// Bit 0 is the most significant bit of the first byte (`0x80`).
+    ///
+    /// This is the ordering used by the `&[u8]` encoder and decoder methods.
+    MsbFirst,
+
+    /// Bit 0 is the least significant bit of the first byte (`0x01`).
+    LsbFirst,
+}
+
+impl BitOrder {
+    /// Returns the byte index and bit mask for bit `idx`.
+    #[inline]
+    fn locate(&self, idx: usize) -> (usize, u8) {
+        match *self {
+            BitOrder::MsbFirst => (idx / 8, 1 << (7 - (idx % 8))),
+            BitOrder::LsbFirst => (idx / 8, 1 << (idx % 8)),
+        }
+    }
+}
+
+/// Immutable view of `len` bits of a byte buffer, starting at bit `offset`.
+#[derive(Copy,Clone,Debug)]
+pub struct BitSlice<'a> {
+    data: &'a [u8],
+    offset: usize,
+    len: usize,
+    order: BitOrder,
+}
+
+/// Mutable view of `len` bits of a byte buffer, starting at bit `offset`.
+#[derive(Debug)]
+pub struct BitSliceMut<'a> {
+    data: &'a mut [u8],
+    offset: usize,
+    len: usize,
+    order: BitOrder,
+}
+
+impl<'a> BitSlice<'a> {
+    /// Create a new view of `len` bits of `data`, starting at bit `offset`.
+    ///
+    /// Panics if the view would extend beyond the end of `data`.
+    pub fn new(data: &'a [u8], offset: usize, len: usize, order: BitOrder) -> BitSlice<'a> {
+        assert!(offset + len <= data.len() * 8, "bit slice extends beyond end of data");
+        BitSlice { data, offset, len, order }
+    }
+
+    /// Number of bits in this view.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if this view contains no bits.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Bit ordering used by this view.
+    pub fn order(&self) -> BitOrder {
+        self.order
+    }
+
+    /// Get bit `idx` of this view.
+    #[inline]
+    pub fn get(&self, idx: usize) -> bool {
+        assert!(idx < self.len, "bit index out of range");
+        let (byte, mask) = self.order.locate(self.offset + idx);
+        self.data[byte] & mask != 0
+    }
+
+    /// Get a sub-view of `len` bits starting at bit `start` of this view.
+    pub fn slice(&self, start: usize, len: usize) -> BitSlice<'a> {
+        assert!(start + len <= self.len, "sub-slice extends beyond end of bit slice");
+        BitSlice { data: self.data, offset: self.offset + start, len, order: self.order }
+    }
+}
+
+impl<'a> BitSliceMut<'a> {
+    /// Create a new mutable view of `len` bits of `data`, starting at bit `offset`.
+    ///
+    /// Panics if the view would extend beyond the end of `data`.
+    pub fn new(data: &'a mut [u8], offset: usize, len: usize, order: BitOrder)
+        -> BitSliceMut<'a>
+    {
+        assert!(offset + len <= data.len() * 8, "bit slice extends beyond end of data");
+        BitSliceMut { data, offset, len, order }
+    }
+
+    /// Number of bits in this view.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if this view contains no bits.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Bit ordering used by this view.
+    pub fn order(&self) -> BitOrder {
+        self.order
+    }
+
+    /// Get bit `idx` of this view.
+    #[inline]
+    pub fn get(&self, idx: usize) -> bool {
+        assert!(idx < self.len, "bit index out of range");
+        let (byte, mask) = self.order.locate(self.offset + idx);
+        self.data[byte] & mask != 0
+    }
+
+    /// Set bit `idx` of this view to `val`, leaving all other bits untouched.
+    #[inline]
+    pub fn set(&mut self, idx: usize, val: bool) {
+        assert!(idx < self.len, "bit index out of range");
+        let (byte, mask) = self.order.locate(self.offset + idx);
+        if val {
+            self.data[byte] |= mask;
+        } else {
+            self.data[byte] &= !mask;
+        }
+    }
+
+    /// Invert bit `idx` of this view.
+    #[inline]
+    pub fn toggle(&mut self, idx: usize) {
+        assert!(idx < self.len, "bit index out of range");
+        let (byte, mask) = self.order.locate(self.offset + idx);
+        self.data[byte] ^= mask;
+    }
+
+    /// Get an immutable view of the same bits.
+    pub fn as_bitslice(&self) -> BitSlice<'_> {
+        BitSlice { data: self.data, offset: self.offset, len: self.len, order: self.order }
+    }
+
+    /// Get a mutable sub-view of `len` bits starting at bit `start` of this view.
+    pub fn slice_mut(&mut self, start: usize, len: usize) -> BitSliceMut<'_> {
+        assert!(start + len <= self.len, "sub-slice extends beyond end of bit slice");
+        BitSliceMut { data: self.data, offset: self.offset + start, len, order: self.order }
+    }
+
+    /// Copy every bit of `src` into this view. Both must be the same length.
+    pub fn copy_from(&mut self, src: &BitSlice) {
+        assert_eq!(src.len(), self.len, "bit slices must be the same length");
+        for idx in 0..self.len {
+            self.set(idx, src.get(idx));
+        }
+    }
+
+    /// Copy the first `self.len()` bits of the MSB-first packed `src` into this view.
+    ///
+    /// Useful for writing decoder output (which is always aligned and MSB-first) back into
+    /// an unaligned position in a larger frame.
+    pub fn copy_from_bytes(&mut self, src: &[u8]) {
+        let len = self.len;
+        self.copy_from(&BitSlice::new(src, 0, len, BitOrder::MsbFirst));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BitOrder, BitSlice, BitSliceMut};
+
+    #[test]
+    fn test_bitslice_get() {
+        let data = [0b1010_0000, 0b0000_0101];
+        let msb = BitSlice::new(&data, 0, 16, BitOrder::MsbFirst);
+        let lsb = BitSlice::new(&data, 0, 16, BitOrder::LsbFirst);
+        assert!(msb.get(0) && !msb.get(1) && msb.get(2));
+        assert!(msb.get(13) && !msb.get(14) && msb.get(15));
+        assert!(lsb.get(5) && !lsb.get(6) && lsb.get(7));
+        assert!(lsb.get(8) && !lsb.get(9) && lsb.get(10));
+
+        let sub = msb.slice(2, 12);
+        assert_eq!(sub.len(), 12);
+        assert!(sub.get(0) && !sub.get(1) && sub.get(11));
+    }
+
+    #[test]
+    fn test_bitslice_set() {
+        let mut data = [0u8; 3];
+        {
+            let mut bits = BitSliceMut::new(&mut data, 4, 16, BitOrder::MsbFirst);
+            bits.set(0, true);
+            bits.set(15, true);
+            bits.toggle(7);
+        }
+        assert_eq!(data, [0b0000_1000, 0b0001_0000, 0b0001_0000]);
+
+        let mut data = [0u8; 2];
+        {
+            let mut bits = BitSliceMut::new(&mut data, 3, 8, BitOrder::LsbFirst);
+            bits.copy_from_bytes(&[0b1000_0001]);
+        }
+        assert_eq!(data, [0b0000_1000, 0b0000_0100]);
+    }
+}
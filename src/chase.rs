@@ -0,0 +1,232 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! This module provides Chase-II list decoding, using `decode_bf` as the inner decoder.
+//!
+//! The `test_bits` least reliable received bits are found, and the bit flipping decoder is run
+//! once for each of the 2^`test_bits` patterns of flips on those bits. Every codeword found is
+//! scored by its correlation discrepancy, the sum of |llr| over the bits where it disagrees
+//! with the hard decisions, and the lowest scoring (most likely) codeword is output.
+//!
+//! Chase decoding is not guaranteed to find the maximum likelihood codeword, but with enough
+//! test bits on a short code such as TC128 it very rarely misses it, and the discrepancy and
+//! margin over the runner-up in `ChaseResult` indicate how much to trust the result. It is
+//! much slower than `decode_ms`, so is best suited to ground-side verification of marginal
+//! frames, or to cross-checking `decode_ms` output.
+//!
+//! ```
+//! # use labrador_ldpc::LDPCCode;
+//! let code = LDPCCode::TC128;
+//! let txdata: Vec<u8> = (0..8).collect();
+//! let mut txcode = vec![0u8; code.n()/8];
+//! code.copy_encode(&txdata, &mut txcode);
+//!
+//! let mut llrs = vec![0f32; code.n()];
+//! code.hard_to_llrs(&txcode, &mut llrs);
+//! for i in 0..6 {
+//!     llrs[i * 20] *= -0.5;
+//! }
+//!
+//! let mut working = vec![0u8; code.decode_bf_working_len()];
+//! let mut chase_working = vec![0u8; code.decode_chase_working_len()];
+//! let mut rxdata = vec![0u8; code.output_len()];
+//! let result = code.decode_chase(&llrs, &mut rxdata, &mut working, &mut chase_working, 8, 20);
+//! assert!(result.success);
+//! assert_eq!(result.discrepancy, 3.0);
+//! assert_eq!(&rxdata[..8], &txdata[..]);
+//! ```
+
+use core::f32;
+
+use ::codes::LDPCCode;
+use ::decoder::FloatConvert;
+use ::osd::discrepancy_xor;
+
+/// Maximum number of test bits for `decode_chase`.
+pub const MAX_TEST_BITS: usize = 16;
+
+/// Result of Chase decoding.
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub struct ChaseResult {
+    /// True if any test pattern decoded to a codeword.
+    pub success: bool,
+
+    /// Correlation discrepancy of the output codeword, or infinity if none was found.
+    pub discrepancy: f32,
+
+    /// Discrepancy of the best other codeword found minus that of the output codeword,
+    /// or infinity if no other codeword was found. A small margin means the choice between
+    /// the two was close.
+    pub margin: f32,
+
+    /// Number of test patterns which decoded to a codeword.
+    pub decoded: usize,
+}
+
+impl LDPCCode {
+    /// Get the length of [u8] required for the working area of `decode_chase`.
+    ///
+    /// Equal to n/8 + (n+punctured_bits)/8.
+    pub fn decode_chase_working_len(&self) -> usize {
+        self.n() / 8 + self.output_len()
+    }
+
+    /// Decode using Chase-II test patterns on the `test_bits` least reliable bits.
+    ///
+    /// * `llrs` must be n long
+    /// * `output` must be allocated to (n+punctured_bits)/8 bytes, aka `output_len()`, and is
+    ///   written with the best codeword found, or the hard decisions if none was found
+    /// * `working` must be allocated to `decode_bf_working_len()` bytes
+    /// * `chase_working` must be allocated to `decode_chase_working_len()` bytes
+    /// * `test_bits` may be at most `MAX_TEST_BITS`
+    /// * `maxiters` is the maximum number of iterations for each run of the bit flipping decoder
+    ///
+    /// Runs `decode_bf` 2^`test_bits` times, so the run time doubles with each extra test bit.
+    pub fn decode_chase<T: FloatConvert>(&self, llrs: &[T], output: &mut [u8], working: &mut [u8],
+                                       chase_working: &mut [u8], test_bits: usize,
+                                       maxiters: usize) -> ChaseResult
+    {
+        let n = self.n();
+        assert_eq!(llrs.len(), n, "llrs.len() != n");
+        assert_eq!(output.len(), self.output_len(), "output.len() != output_len()");
+        assert_eq!(working.len(), self.decode_bf_working_len(), "working.len() incorrect");
+        assert_eq!(chase_working.len(), self.decode_chase_working_len(),
+                   "chase_working.len() != decode_chase_working_len()");
+        assert!(test_bits <= MAX_TEST_BITS, "test_bits must be at most MAX_TEST_BITS");
+
+        let (hard, candidate) = chase_working.split_at_mut(n / 8);
+        self.llrs_to_hard(llrs, hard);
+
+        // Find the least reliable bits, least reliable first
+        let mut positions = [0usize; MAX_TEST_BITS];
+        for t in 0..test_bits {
+            let mut best = n;
+            for (i, llr) in llrs.iter().enumerate() {
+                if positions[..t].contains(&i) {
+                    continue;
+                }
+                if best == n || llr.abs() < llrs[best].abs() {
+                    best = i;
+                }
+            }
+            positions[t] = best;
+        }
+
+        let mut result = ChaseResult {
+            success: false, discrepancy: f32::INFINITY, margin: f32::INFINITY, decoded: 0,
+        };
+        let mut second = f32::INFINITY;
+        output[..n/8].copy_from_slice(hard);
+
+        for pattern in 0..(1usize << test_bits) {
+            candidate[..n/8].copy_from_slice(hard);
+            for (t, &i) in positions[..test_bits].iter().enumerate() {
+                if pattern >> t & 1 == 1 {
+                    candidate[i/8] ^= 1 << (7 - (i%8));
+                }
+            }
+
+            let (success, _) = self.decode_bf_inplace(candidate, working, maxiters);
+            if !success {
+                continue;
+            }
+            result.decoded += 1;
+
+            if result.success && candidate[..] == output[..] {
+                continue;
+            }
+            let score = discrepancy_xor(llrs, hard, &candidate[..n/8]);
+            if score < result.discrepancy {
+                second = result.discrepancy;
+                result.discrepancy = score;
+                output.copy_from_slice(candidate);
+                result.success = true;
+            } else if score < second {
+                second = score;
+            }
+        }
+
+        if result.success {
+            result.margin = second - result.discrepancy;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+
+    use ::codes::LDPCCode;
+    use ::sim::{AwgnChannel, Modulation, Rng};
+
+    #[test]
+    fn test_chase() {
+        let code = LDPCCode::TC128;
+        let mut ch = AwgnChannel::new(code, Modulation::BPSK, 3.0, 5);
+        let mut rng = Rng::new(6);
+        let mut working = vec![0u8; code.decode_bf_working_len()];
+        let mut chase_working = vec![0u8; code.decode_chase_working_len()];
+        let mut output = vec![0u8; code.output_len()];
+        let mut correct = [0; 2];
+
+        for _ in 0..40 {
+            let mut txdata = vec![0u8; code.k()/8];
+            rng.fill_bytes(&mut txdata);
+            let mut txcode = vec![0u8; code.n()/8];
+            code.copy_encode(&txdata, &mut txcode);
+            let mut llrs = vec![0f32; code.n()];
+            ch.transmit(&txcode, &mut llrs, 1.0);
+
+            // With no test bits, Chase decoding is just the bit flipping decoder
+            let mut hard = vec![0u8; code.n()/8];
+            code.llrs_to_hard(&llrs, &mut hard);
+            let (success, _) = code.decode_bf(&hard, &mut output, &mut working, 20);
+            let result = code.decode_chase(&llrs, &mut output, &mut working,
+                                           &mut chase_working, 0, 20);
+            assert_eq!(result.success, success);
+            assert_eq!(result.margin, f32::INFINITY);
+            correct[0] += (success && output == txcode) as usize;
+
+            let result = code.decode_chase(&llrs, &mut output, &mut working,
+                                           &mut chase_working, 6, 20);
+            if result.success {
+                assert!(result.decoded > 0);
+                assert!(result.margin > 0.0);
+            }
+            correct[1] += (output == txcode) as usize;
+        }
+
+        assert!(correct[1] > correct[0]);
+    }
+
+    #[test]
+    fn test_chase_erasures() {
+        let code = LDPCCode::TC128;
+        let mut working = vec![0u8; code.decode_bf_working_len()];
+        let mut chase_working = vec![0u8; code.decode_chase_working_len()];
+        let mut output = vec![0u8; code.output_len()];
+        let mut bf_output = vec![0u8; code.output_len()];
+
+        let txdata: Vec<u8> = (0..code.k()/8).map(|i| (i * 37) as u8).collect();
+        let mut txcode = vec![0u8; code.n()/8];
+        code.copy_encode(&txdata, &mut txcode);
+        let mut llrs = vec![0i16; code.n()];
+        code.hard_to_llrs(&txcode, &mut llrs);
+
+        // Zero LLRs are hard decided as 0, as by `llrs_to_hard`, so erasing enough of the ones
+        // leaves hard decisions the bit flipping decoder cannot correct
+        let ones: Vec<usize> = (0..code.n()).filter(|&i| llrs[i] < 0).collect();
+        for i in ones.iter().step_by(4) {
+            llrs[*i] = 0;
+        }
+        let mut hard = vec![0u8; code.n()/8];
+        code.llrs_to_hard(&llrs, &mut hard);
+        let (success, _) = code.decode_bf(&hard, &mut bf_output, &mut working, 20);
+        assert!(!success);
+        let result = code.decode_chase(&llrs, &mut output, &mut working, &mut chase_working,
+                                       0, 20);
+        assert!(!result.success);
+        assert_eq!(&output[..code.n()/8], &hard[..]);
+    }
+}
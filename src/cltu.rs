@@ -0,0 +1,272 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! This module provides construction and parsing of Communications Link Transmission Units
+//! (CLTUs) for the TC codes, as defined in CCSDS 231.0-B.
+//!
+//! A CLTU carries one telecommand transfer frame and consists of:
+//!
+//! * the 64-bit start sequence 0x034776C7272895B0,
+//! * one or more codeblocks, each an LDPC codeword whose k information bits are the next k
+//!   bits of the frame, with the last codeblock padded with alternating 0/1 fill bits (0x55),
+//! * a tail sequence of one codeblock length, a fixed pattern chosen so that it never
+//!   decodes as a valid codeword.
+//!
+//! The tail sequence is 0x55555556AAAAAAAA5555555555555555 for TC128, and the same pattern
+//! repeated to fill the codeblock for TC256 and TC512.
+//!
+//! On receive, `decode_cltu` hunts for the start sequence in a stream of LLRs, then decodes
+//! codeblocks until one fails to decode (normally the tail sequence) or the stream ends, and
+//! reassembles the frame. The fill bits cannot be distinguished from frame data, so any
+//! fill remains at the end of the output and should be removed using the frame length in
+//! the transfer frame header.
+//!
+//! If the frame is to be randomized, apply
+//! [`Randomizer::TC`](../randomizer/enum.Randomizer.html) to it before calling `encode_cltu`
+//! and after `decode_cltu`.
+//!
+//! ```
+//! # use labrador_ldpc::LDPCCode;
+//! let code = LDPCCode::TC128;
+//! let frame: Vec<u8> = (0..20).collect();
+//!
+//! let mut cltu = vec![0u8; code.cltu_len(frame.len())];
+//! code.encode_cltu(&frame, &mut cltu);
+//!
+//! let mut llrs: Vec<i8> = (0..cltu.len()*8)
+//!     .map(|i| if cltu[i/8] >> (7 - (i%8)) & 1 == 1 { -1 } else { 1 }).collect();
+//!
+//! let mut rxframe = vec![0u8; 64];
+//! let mut output = vec![0u8; code.output_len()];
+//! let mut working = vec![0i8; code.decode_ms_working_len()];
+//! let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+//! let result = code.decode_cltu(&mut llrs, &mut rxframe, &mut output, &mut working,
+//!                               &mut working_u8, 20, 0.8).unwrap();
+//! assert_eq!(result.codeblocks, 3);
+//! assert_eq!(&rxframe[..20], &frame[..]);
+//! ```
+
+use ::asm::Asm;
+use ::codes::LDPCCode;
+use ::decoder::{FloatConvert, saturating_neg};
+
+/// CLTU start sequence for the LDPC-coded TC codes.
+const START_SEQUENCE: Asm = Asm::Asm64;
+
+/// Fill byte used to pad the final codeblock.
+const FILL: u8 = 0x55;
+
+/// One 128-bit period of the CLTU tail sequence.
+static TAIL: [u8; 16] = [0x55, 0x55, 0x55, 0x56, 0xAA, 0xAA, 0xAA, 0xAA,
+                         0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55];
+
+/// Result of decoding a CLTU.
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub struct CltuResult {
+    /// Index of the first bit of the start sequence in the searched LLRs.
+    pub start: usize,
+
+    /// True if the start sequence was found with inverted polarity.
+    ///
+    /// In this case all the codeblock LLRs have been negated in place.
+    pub inverted: bool,
+
+    /// Number of codeblocks successfully decoded.
+    pub codeblocks: usize,
+
+    /// Number of bytes written to the frame output, including any fill bytes.
+    pub frame_len: usize,
+
+    /// Index of the first LLR after the end of the CLTU, including the tail sequence if one
+    /// was found.
+    pub end: usize,
+}
+
+impl LDPCCode {
+    /// Get the number of codeblocks required to carry a frame of `frame_len` bytes.
+    pub fn cltu_codeblocks(&self, frame_len: usize) -> usize {
+        let k = self.k() / 8;
+        frame_len.div_ceil(k)
+    }
+
+    /// Get the length in bytes of the CLTU carrying a frame of `frame_len` bytes.
+    ///
+    /// Equal to 8 + (codeblocks + 1) * n/8, for the start sequence, codeblocks and tail.
+    pub fn cltu_len(&self, frame_len: usize) -> usize {
+        START_SEQUENCE.bits()/8 + (self.cltu_codeblocks(frame_len) + 1) * self.n()/8
+    }
+
+    /// Build a CLTU carrying `frame`.
+    ///
+    /// Only the TC codes may be used. `cltu` must be exactly `cltu_len(frame.len())` bytes,
+    /// and is written with the start sequence, the encoded codeblocks (with the final one
+    /// padded with fill bytes), and the tail sequence.
+    pub fn encode_cltu(&self, frame: &[u8], cltu: &mut [u8]) {
+        assert_eq!(self.punctured_bits(), 0, "CLTUs are only defined for the TC codes");
+        assert_eq!(cltu.len(), self.cltu_len(frame.len()), "cltu.len() incorrect");
+
+        let k = self.k() / 8;
+        let n = self.n() / 8;
+
+        let (start, cltu) = cltu.split_at_mut(START_SEQUENCE.bits()/8);
+        start.copy_from_slice(START_SEQUENCE.bytes());
+
+        for (chunk, codeblock) in frame.chunks(k).zip(cltu.chunks_mut(n)) {
+            codeblock[..chunk.len()].copy_from_slice(chunk);
+            for x in &mut codeblock[chunk.len()..k] { *x = FILL }
+            self.encode(codeblock);
+        }
+
+        let tail = cltu.len() - n;
+        for (x, t) in cltu[tail..].iter_mut().zip(TAIL.iter().cycle()) {
+            *x = *t;
+        }
+    }
+
+    /// Find and decode a CLTU in a stream of LLRs.
+    ///
+    /// Searches `llrs` for the start sequence, using the same normalised correlation and
+    /// `threshold` as [`Asm::find`](../asm/enum.Asm.html#method.find). Each following block of
+    /// n LLRs is then decoded with `decode_ms`, running for at most `maxiters` iterations,
+    /// until one fails to decode (normally the tail sequence), the LLRs run out, or `frame` is
+    /// full. The information bytes of each decoded codeblock are written consecutively to
+    /// `frame`.
+    ///
+    /// * `output`, `working` and `working_u8` are as for `decode_ms`.
+    /// * If the start sequence is inverted, the LLRs for each codeblock are negated in place.
+    ///
+    /// Returns `None` if no start sequence followed by at least one codeblock was found,
+    /// otherwise details of the decoded CLTU. If no codeblocks decoded successfully, the
+    /// returned `codeblocks` is 0.
+    #[allow(clippy::too_many_arguments)]
+    pub fn decode_cltu<T: FloatConvert>(&self, llrs: &mut [T], frame: &mut [u8],
+                                      output: &mut [u8], working: &mut [T],
+                                      working_u8: &mut [u8], maxiters: usize, threshold: f32)
+        -> Option<CltuResult>
+    {
+        assert_eq!(self.punctured_bits(), 0, "CLTUs are only defined for the TC codes");
+
+        let n = self.n();
+        let k = self.k() / 8;
+
+        let sync = START_SEQUENCE.find(llrs, n, threshold)?;
+        let mut result = CltuResult {
+            start: sync.offset, inverted: sync.inverted, codeblocks: 0, frame_len: 0,
+            end: sync.offset + START_SEQUENCE.bits(),
+        };
+
+        while result.end + n <= llrs.len() && result.frame_len + k <= frame.len() {
+            let codeblock = &mut llrs[result.end..result.end + n];
+            if sync.inverted {
+                for llr in codeblock.iter_mut() {
+                    *llr = saturating_neg(*llr);
+                }
+            }
+
+            // A codeblock that fails to decode marks the end of the CLTU
+            result.end += n;
+            let (success, _) = self.decode_ms(codeblock, output, working, working_u8, maxiters);
+            if !success {
+                break;
+            }
+
+            frame[result.frame_len..result.frame_len + k].copy_from_slice(&output[..k]);
+            result.frame_len += k;
+            result.codeblocks += 1;
+        }
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+
+    use ::codes::LDPCCode;
+
+    const CODES: [LDPCCode; 3] = [LDPCCode::TC128, LDPCCode::TC256, LDPCCode::TC512];
+
+    fn to_llrs(bytes: &[u8]) -> Vec<i8> {
+        (0..bytes.len()*8).map(|i| if bytes[i/8] >> (7 - (i%8)) & 1 == 1 { -1 } else { 1 })
+                          .collect()
+    }
+
+    #[test]
+    fn test_encode_cltu() {
+        let code = LDPCCode::TC128;
+        let frame: Vec<u8> = (0..10).collect();
+        let mut cltu = vec![0u8; code.cltu_len(frame.len())];
+        assert_eq!(cltu.len(), 8 + 3*16);
+        code.encode_cltu(&frame, &mut cltu);
+
+        assert_eq!(&cltu[..8], &[0x03, 0x47, 0x76, 0xC7, 0x27, 0x28, 0x95, 0xB0]);
+        assert_eq!(&cltu[8..16], &frame[..8]);
+        assert_eq!(&cltu[24..26], &frame[8..]);
+        assert_eq!(&cltu[26..32], &[0x55; 6]);
+        assert_eq!(&cltu[40..44], &[0x55, 0x55, 0x55, 0x56]);
+
+        // Check each codeblock is a valid codeword
+        for codeblock in cltu[8..40].chunks(16) {
+            let mut check = codeblock.to_vec();
+            code.encode(&mut check);
+            assert_eq!(&check[..], codeblock);
+        }
+    }
+
+    #[test]
+    fn test_tail_does_not_decode() {
+        for code in &CODES {
+            let mut cltu = vec![0u8; code.cltu_len(1)];
+            code.encode_cltu(&[0], &mut cltu);
+            let llrs = to_llrs(&cltu[cltu.len() - code.n()/8..]);
+            let mut output = vec![0u8; code.output_len()];
+            let mut working = vec![0i8; code.decode_ms_working_len()];
+            let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+            let (success, _) = code.decode_ms(&llrs, &mut output, &mut working,
+                                              &mut working_u8, 50);
+            assert!(!success);
+        }
+    }
+
+    #[test]
+    fn test_decode_cltu() {
+        for code in &CODES {
+            let frame: Vec<u8> = (0..100).map(|i| (i * 13) as u8).collect();
+            let mut cltu = vec![0u8; code.cltu_len(frame.len())];
+            code.encode_cltu(&frame, &mut cltu);
+
+            // Preceding junk, a corrupted bit in each codeblock, and inverted polarity
+            let mut stream = vec![0xAAu8, 0x0F];
+            stream.extend_from_slice(&cltu);
+            stream.extend_from_slice(&[0xAA; 40]);
+            let mut llrs: Vec<i8> = to_llrs(&stream).iter().map(|x| -x).collect();
+            for block in 0..code.cltu_codeblocks(frame.len()) {
+                let idx = 16 + 64 + block*code.n() + 3;
+                llrs[idx] = -llrs[idx];
+            }
+
+            // A confident LLR at i8::MIN, which must not overflow when un-inverted
+            let idx = (16 + 64..).find(|&i| llrs[i] < 0).unwrap();
+            llrs[idx] = i8::MIN;
+
+            let mut rxframe = vec![0u8; 200];
+            let mut output = vec![0u8; code.output_len()];
+            let mut working = vec![0i8; code.decode_ms_working_len()];
+            let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+            let result = code.decode_cltu(&mut llrs, &mut rxframe, &mut output, &mut working,
+                                          &mut working_u8, 50, 0.8).unwrap();
+
+            let blocks = code.cltu_codeblocks(frame.len());
+            assert_eq!(result.start, 16);
+            assert!(result.inverted);
+            assert_eq!(result.codeblocks, blocks);
+            assert_eq!(result.frame_len, blocks * code.k()/8);
+            assert_eq!(result.end, 16 + cltu.len()*8);
+            assert_eq!(&rxframe[..frame.len()], &frame[..]);
+            for x in &rxframe[frame.len()..result.frame_len] {
+                assert_eq!(*x, 0x55);
+            }
+        }
+    }
+}
@@ -0,0 +1,293 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! This module provides CRC computation and CRC-aided decoding.
+//!
+//! The min-sum decoder stops as soon as every parity check is satisfied, but very occasionally
+//! the codeword it has found is not the one that was transmitted. If the message carries a
+//! CRC in its final bytes, `decode_ms_crc` checks it each time a codeword is found, and keeps
+//! iterating if it does not match, in the hope of converging to the correct codeword.
+//!
+//! Two kinds of CRC are available:
+//!
+//! * `Crc::Ccitt16` is the CRC-16-CCITT used for the Frame Error Control Field of CCSDS TC
+//!   transfer frames: polynomial 0x1021, initial value 0xFFFF, no reflection or final XOR.
+//! * `Crc::Crc32` takes a `Crc32Params` describing any 32-bit CRC. `CRC32_ISO_HDLC` (the
+//!   common Ethernet/zip CRC-32) and `CRC32C` are provided.
+//!
+//! The CRC is stored at the end of the k/8-byte message, most significant byte first for
+//! non-reflected CRCs and least significant byte first for reflected CRCs, as is conventional.
+//!
+//! ```
+//! # use labrador_ldpc::LDPCCode;
+//! use labrador_ldpc::crc::{Crc, CrcStatus};
+//!
+//! let code = LDPCCode::TC256;
+//! let crc = Crc::Ccitt16;
+//!
+//! // The last two bytes of the message hold the CRC of the rest
+//! let mut txcode = vec![0u8; code.n()/8];
+//! for (i, x) in txcode[..14].iter_mut().enumerate() { *x = i as u8 }
+//! crc.append(&mut txcode[..code.k()/8]);
+//! code.encode(&mut txcode);
+//!
+//! let mut llrs = vec![0i8; code.n()];
+//! code.hard_to_llrs(&txcode, &mut llrs);
+//! llrs[3] = -llrs[3];
+//!
+//! let mut working = vec![0i8; code.decode_ms_working_len()];
+//! let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+//! let mut rxdata = vec![0u8; code.output_len()];
+//! let (status, _) = code.decode_ms_crc(crc, &llrs, &mut rxdata, &mut working,
+//!                                      &mut working_u8, 20);
+//! assert_eq!(status, CrcStatus::Valid);
+//! assert_eq!(&rxdata[..16], &txcode[..16]);
+//! ```
+
+use ::codes::LDPCCode;
+use ::decoder::DecodeFrom;
+
+/// Parameters describing a 32-bit CRC.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub struct Crc32Params {
+    /// Generator polynomial, in normal (not reversed) form, without the leading x^32 term.
+    pub poly: u32,
+
+    /// Initial value of the CRC register.
+    pub init: u32,
+
+    /// If true, each input byte and the final CRC are bit-reversed (LSB-first processing).
+    pub reflect: bool,
+
+    /// Value XORed into the final CRC.
+    pub xorout: u32,
+}
+
+/// The common CRC-32 used by Ethernet, zip and PNG, also known as CRC-32/ISO-HDLC.
+pub const CRC32_ISO_HDLC: Crc32Params = Crc32Params {
+    poly: 0x04C1_1DB7, init: 0xFFFF_FFFF, reflect: true, xorout: 0xFFFF_FFFF,
+};
+
+/// CRC-32C (Castagnoli), as used by iSCSI and SCTP.
+pub const CRC32C: Crc32Params = Crc32Params {
+    poly: 0x1EDC_6F41, init: 0xFFFF_FFFF, reflect: true, xorout: 0xFFFF_FFFF,
+};
+
+/// Available CRCs.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub enum Crc {
+    /// CRC-16-CCITT as used for the CCSDS TC Frame Error Control Field.
+    Ccitt16,
+
+    /// A 32-bit CRC with the given parameters.
+    Crc32(Crc32Params),
+}
+
+/// Result of CRC-aided decoding.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub enum CrcStatus {
+    /// A codeword was found whose CRC matches.
+    Valid,
+
+    /// Codewords were found but none had a matching CRC.
+    Invalid,
+
+    /// No codeword was found within the iteration limit.
+    NoCodeword,
+}
+
+impl Crc {
+    /// Get the width of the CRC in bits.
+    pub fn width(&self) -> usize {
+        match *self {
+            Crc::Ccitt16 => 16,
+            Crc::Crc32(_) => 32,
+        }
+    }
+
+    /// Compute the CRC over `data`.
+    pub fn compute(&self, data: &[u8]) -> u32 {
+        match *self {
+            Crc::Ccitt16 => {
+                let mut crc: u16 = 0xFFFF;
+                for &byte in data {
+                    crc ^= (byte as u16) << 8;
+                    for _ in 0..8 {
+                        crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+                    }
+                }
+                crc as u32
+            },
+            Crc::Crc32(params) => {
+                let mut crc = params.init;
+                if params.reflect {
+                    let poly = params.poly.reverse_bits();
+                    crc = crc.reverse_bits();
+                    for &byte in data {
+                        crc ^= byte as u32;
+                        for _ in 0..8 {
+                            crc = if crc & 1 != 0 { (crc >> 1) ^ poly } else { crc >> 1 };
+                        }
+                    }
+                } else {
+                    for &byte in data {
+                        crc ^= (byte as u32) << 24;
+                        for _ in 0..8 {
+                            crc = if crc & 0x8000_0000 != 0 {
+                                (crc << 1) ^ params.poly
+                            } else {
+                                crc << 1
+                            };
+                        }
+                    }
+                }
+                crc ^ params.xorout
+            },
+        }
+    }
+
+    /// Compute the CRC over all but the last `self.width()/8` bytes of `message`, and write it
+    /// into those last bytes.
+    pub fn append(&self, message: &mut [u8]) {
+        let len = self.width() / 8;
+        assert!(message.len() >= len, "message shorter than CRC");
+        let split = message.len() - len;
+        let crc = self.compute(&message[..split]);
+        for (i, x) in message[split..].iter_mut().enumerate() {
+            *x = (crc >> self.shift(i)) as u8;
+        }
+    }
+
+    /// Check the CRC stored in the last `self.width()/8` bytes of `message` against the rest.
+    pub fn check(&self, message: &[u8]) -> bool {
+        let len = self.width() / 8;
+        if message.len() < len {
+            return false;
+        }
+        let split = message.len() - len;
+        let crc = self.compute(&message[..split]);
+        message[split..].iter().enumerate().all(|(i, &x)| x == (crc >> self.shift(i)) as u8)
+    }
+
+    /// Bit shift to extract the `i`th stored byte of the CRC.
+    fn shift(&self, i: usize) -> usize {
+        let reflect = match *self {
+            Crc::Ccitt16 => false,
+            Crc::Crc32(params) => params.reflect,
+        };
+        if reflect { 8 * i } else { 8 * (self.width()/8 - 1 - i) }
+    }
+}
+
+impl LDPCCode {
+    /// Run the min-sum decoder, checking `crc` over the decoded message.
+    ///
+    /// The CRC must occupy the last `crc.width()/8` bytes of the k/8-byte message. Whenever all
+    /// parity checks are satisfied the CRC is checked, and if it does not match, decoding
+    /// continues for up to `maxiters` iterations in total.
+    ///
+    /// All other arguments are as for `decode_ms`, and `output` is likewise written with the
+    /// final hard decisions.
+    ///
+    /// Returns the CRC status and the number of iterations run for.
+    pub fn decode_ms_crc<T: DecodeFrom>(&self, crc: Crc, llrs: &[T], output: &mut [u8],
+                                        working: &mut [T], working_u8: &mut [u8],
+                                        maxiters: usize)
+        -> (CrcStatus, usize)
+    {
+        let k = self.k() / 8;
+        assert!(k > crc.width()/8, "CRC longer than message");
+
+        let mut found = false;
+        let (valid, iters) = self.decode_ms_core(llrs, output, working, working_u8, 0, maxiters,
+                                                 |decoded| {
+            found = true;
+            crc.check(&decoded[..k])
+        }, |_, _| false);
+
+        let status = if valid {
+            CrcStatus::Valid
+        } else if found {
+            CrcStatus::Invalid
+        } else {
+            CrcStatus::NoCodeword
+        };
+        (status, iters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+
+    use ::codes::LDPCCode;
+    use super::{Crc, CrcStatus, Crc32Params, CRC32_ISO_HDLC, CRC32C};
+
+    #[test]
+    fn test_check_values() {
+        let data = b"123456789";
+        assert_eq!(Crc::Ccitt16.compute(data), 0x29B1);
+        assert_eq!(Crc::Crc32(CRC32_ISO_HDLC).compute(data), 0xCBF4_3926);
+        assert_eq!(Crc::Crc32(CRC32C).compute(data), 0xE306_9283);
+
+        // CRC-32/BZIP2 exercises the non-reflected path
+        let bzip2 = Crc32Params { poly: 0x04C1_1DB7, init: 0xFFFF_FFFF, reflect: false,
+                                  xorout: 0xFFFF_FFFF };
+        assert_eq!(Crc::Crc32(bzip2).compute(data), 0xFC89_1918);
+    }
+
+    #[test]
+    fn test_append_check() {
+        for crc in &[Crc::Ccitt16, Crc::Crc32(CRC32_ISO_HDLC)] {
+            let mut message: Vec<u8> = (0..20).collect();
+            crc.append(&mut message);
+            assert!(crc.check(&message));
+            message[3] ^= 0x10;
+            assert!(!crc.check(&message));
+        }
+
+        let mut message = b"123456789\x00\x00".to_vec();
+        Crc::Ccitt16.append(&mut message);
+        assert_eq!(&message[9..], &[0x29, 0xB1]);
+    }
+
+    #[test]
+    fn test_decode_ms_crc() {
+        let code = LDPCCode::TM1280;
+        let crc = Crc::Crc32(CRC32_ISO_HDLC);
+        let mut txcode = vec![0u8; code.n()/8];
+        for (i, x) in txcode[..code.k()/8].iter_mut().enumerate() { *x = (i * 7) as u8 }
+        crc.append(&mut txcode[..code.k()/8]);
+        code.encode(&mut txcode);
+
+        let mut llrs = vec![0i16; code.n()];
+        code.hard_to_llrs(&txcode, &mut llrs);
+        for i in 0..8 {
+            llrs[i * 97] = -llrs[i * 97];
+        }
+
+        let mut working = vec![0i16; code.decode_ms_working_len()];
+        let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+        let mut output = vec![0u8; code.output_len()];
+        let (status, _) = code.decode_ms_crc(crc, &llrs, &mut output, &mut working,
+                                             &mut working_u8, 50);
+        assert_eq!(status, CrcStatus::Valid);
+        assert_eq!(&output[..code.n()/8], &txcode[..]);
+
+        // A valid codeword whose CRC doesn't match is never accepted
+        let mut badcode = vec![0u8; code.n()/8];
+        badcode[0] = 1;
+        code.encode(&mut badcode);
+        code.hard_to_llrs(&badcode, &mut llrs);
+        let (status, iters) = code.decode_ms_crc(crc, &llrs, &mut output, &mut working,
+                                                 &mut working_u8, 10);
+        assert_eq!(status, CrcStatus::Invalid);
+        assert_eq!(iters, 10);
+
+        // With no information at all no codeword is found
+        let zeros = vec![0i16; code.n()];
+        let (status, _) = code.decode_ms_crc(crc, &zeros, &mut output, &mut working,
+                                             &mut working_u8, 10);
+        assert_eq!(status, CrcStatus::NoCodeword);
+    }
+}
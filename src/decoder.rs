@@ -4,7 +4,8 @@
 //! This module provides decoding functions for turning codewords into data.
 //!
 //! Please refer to the `decode_ms` and `decode_bf` methods on
-//! [`LDPCCode`](../codes/enum.LDPCCode.html) for more details.
+//! [`LDPCCode`](../codes/enum.LDPCCode.html) for more details, or `decode_bf_bits` and
+//! `hard_bits_to_llrs` for received codewords which are not byte-aligned.
 
 use core::i8;
 use core::i16;
@@ -15,6 +16,8 @@ use core::f64;
 use core::ops::{Add,AddAssign,Neg,Sub};
 
 use ::codes::LDPCCode;
+use ::bits::{BitSlice, BitSliceMut};
+use ::math::expf;
 
 // Ugh gross yuck.
 //
@@ -53,6 +56,27 @@ pub trait DecodeFrom:
     fn saturating_add(&self, other: Self) -> Self;
 }
 
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for i8 {}
+    impl Sealed for i16 {}
+    impl Sealed for i32 {}
+    impl Sealed for f32 {}
+    impl Sealed for f64 {}
+}
+
+/// Conversions to and from f32, for the built-in `DecodeFrom` types.
+///
+/// Implemented for `i8`, `i16`, `i32`, `f32`, and `f64`, and sealed so it cannot be
+/// implemented outside this crate. Functions which need to convert LLRs, such as the
+/// demappers and channel estimators, require it in addition to `DecodeFrom`.
+pub trait FloatConvert: DecodeFrom + sealed::Sealed {
+    /// Convert to f32, used where values must be accumulated without saturating
+    fn to_f32(&self) -> f32;
+    /// Convert from f32, rounding to nearest and saturating to the range -maxval..=maxval
+    fn from_f32(x: f32) -> Self;
+}
+
 impl DecodeFrom for i8 {
     #[inline] fn one()      -> i8 { 1 }
     #[inline] fn zero()     -> i8 { 0 }
@@ -60,6 +84,19 @@ impl DecodeFrom for i8 {
     #[inline] fn abs(&self) -> i8 { i8::abs(*self) }
     #[inline] fn saturating_add(&self, other: Self) -> Self { i8::saturating_add(*self, other) }
 }
+impl FloatConvert for i8 {
+    #[inline] fn to_f32(&self) -> f32 { *self as f32 }
+    #[inline] fn from_f32(x: f32) -> i8 {
+        let r = if x >= 0.0 { x + 0.5 } else { x - 0.5 };
+        if r >= i8::MAX as f32 {
+            i8::MAX
+        } else if r <= -(i8::MAX as f32) {
+            -i8::MAX
+        } else {
+            r as i8
+        }
+    }
+}
 impl DecodeFrom for i16 {
     #[inline] fn one()      -> i16 { 1 }
     #[inline] fn zero()     -> i16 { 0 }
@@ -67,6 +104,19 @@ impl DecodeFrom for i16 {
     #[inline] fn abs(&self) -> i16 { i16::abs(*self) }
     #[inline] fn saturating_add(&self, other: Self) -> Self { i16::saturating_add(*self, other) }
 }
+impl FloatConvert for i16 {
+    #[inline] fn to_f32(&self) -> f32 { *self as f32 }
+    #[inline] fn from_f32(x: f32) -> i16 {
+        let r = if x >= 0.0 { x + 0.5 } else { x - 0.5 };
+        if r >= i16::MAX as f32 {
+            i16::MAX
+        } else if r <= -(i16::MAX as f32) {
+            -i16::MAX
+        } else {
+            r as i16
+        }
+    }
+}
 impl DecodeFrom for i32 {
     #[inline] fn one()      -> i32 { 1 }
     #[inline] fn zero()     -> i32 { 0 }
@@ -74,6 +124,19 @@ impl DecodeFrom for i32 {
     #[inline] fn abs(&self) -> i32 { i32::abs(*self) }
     #[inline] fn saturating_add(&self, other: Self) -> Self { i32::saturating_add(*self, other) }
 }
+impl FloatConvert for i32 {
+    #[inline] fn to_f32(&self) -> f32 { *self as f32 }
+    #[inline] fn from_f32(x: f32) -> i32 {
+        let r = if x >= 0.0 { x + 0.5 } else { x - 0.5 };
+        if r >= i32::MAX as f32 {
+            i32::MAX
+        } else if r <= -(i32::MAX as f32) {
+            -i32::MAX
+        } else {
+            r as i32
+        }
+    }
+}
 impl DecodeFrom for f32 {
     #[inline] fn one()      -> f32 { 1.0 }
     #[inline] fn zero()     -> f32 { 0.0 }
@@ -81,6 +144,10 @@ impl DecodeFrom for f32 {
     #[inline] fn abs(&self) -> f32 { fabsf(*self) }
     #[inline] fn saturating_add(&self, other: Self) -> Self { *self + other }
 }
+impl FloatConvert for f32 {
+    #[inline] fn to_f32(&self) -> f32 { *self }
+    #[inline] fn from_f32(x: f32) -> f32 { x }
+}
 impl DecodeFrom for f64 {
     #[inline] fn one()      -> f64 { 1.0 }
     #[inline] fn zero()     -> f64 { 0.0 }
@@ -88,6 +155,174 @@ impl DecodeFrom for f64 {
     #[inline] fn abs(&self) -> f64 { fabs(*self) }
     #[inline] fn saturating_add(&self, other: Self) -> Self { *self + other }
 }
+impl FloatConvert for f64 {
+    #[inline] fn to_f32(&self) -> f32 { *self as f32 }
+    #[inline] fn from_f32(x: f32) -> f64 { x as f64 }
+}
+
+/// Negate `x`, saturating to `T::maxval()` where the negation is not representable, such as
+/// for `i8::MIN`.
+#[inline]
+pub(crate) fn saturating_neg<T: DecodeFrom>(x: T) -> T {
+    if x < -T::maxval() { T::maxval() } else { -x }
+}
+
+/// Criteria for `decode_ms_early` to give up on a frame before `maxiters` iterations.
+///
+/// Each criterion is disabled when set to zero, so `EarlyStopping::default()` disables all
+/// of them. As a starting point, `stable_iters: 4` and `stall_iters: 8` stop most hopeless
+/// frames within a dozen or so iterations while rarely abandoning a frame that would decode.
+#[derive(Copy,Clone,Debug,Default,PartialEq)]
+pub struct EarlyStopping {
+    /// Stop if the hard decisions have not changed for this many consecutive iterations.
+    pub stable_iters: usize,
+
+    /// Stop if the number of unsatisfied parity checks has not fallen below its lowest value
+    /// so far for this many consecutive iterations.
+    pub stall_iters: usize,
+
+    /// Magnitude at which a marginal LLR is considered saturated.
+    pub saturation_level: f32,
+
+    /// Stop if at least this fraction (0 to 1) of the marginals have reached
+    /// `saturation_level` while parity checks are still unsatisfied, as the decoder is then
+    /// confidently stuck and unlikely to change its mind.
+    pub saturation_fraction: f32,
+}
+
+/// Why `decode_ms_early` stopped.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub enum StopReason {
+    /// A valid codeword was found.
+    Codeword,
+
+    /// `maxiters` iterations ran without finding a codeword.
+    MaxIters,
+
+    /// The hard decisions stopped changing.
+    Stable,
+
+    /// The number of unsatisfied parity checks stopped decreasing.
+    Stalled,
+
+    /// Too many marginals saturated.
+    Saturated,
+}
+
+/// Min-sum decoder which runs a few iterations at a time and resumes later where it left off.
+///
+/// `decode_ms` must run all of its iterations in one call. `MsDecoder` instead keeps hold of
+/// the input LLRs, output and working areas, which between them hold all the decoder state,
+/// so that a real-time scheduler can run it in fixed slices with `run`. Running the same
+/// total number of iterations in any number of slices gives exactly the same result as one
+/// call to `decode_ms`.
+///
+/// ```
+/// # use labrador_ldpc::LDPCCode;
+/// use labrador_ldpc::decoder::MsDecoder;
+///
+/// let code = LDPCCode::TC256;
+/// let txdata: Vec<u8> = (0..code.k()/8).map(|i| i as u8).collect();
+/// let mut txcode = vec![0u8; code.n()/8];
+/// code.copy_encode(&txdata, &mut txcode);
+/// let mut llrs = vec![0i16; code.n()];
+/// code.hard_to_llrs(&txcode, &mut llrs);
+/// llrs[10] = -llrs[10];
+///
+/// let mut working = vec![0i16; code.decode_ms_working_len()];
+/// let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+/// let mut rxdata = vec![0u8; code.output_len()];
+/// let mut decoder = MsDecoder::new(code, &llrs, &mut rxdata, &mut working, &mut working_u8);
+///
+/// // Run one iteration per time slice, giving up after 20
+/// while !decoder.run(1) && decoder.iters() < 20 {
+///     // ... yield to other tasks ...
+/// }
+/// assert!(decoder.is_decoded());
+/// assert_eq!(&decoder.output()[..code.k()/8], &txdata[..]);
+/// ```
+pub struct MsDecoder<'a, T: 'a + DecodeFrom> {
+    code: LDPCCode,
+    llrs: &'a [T],
+    output: &'a mut [u8],
+    working: &'a mut [T],
+    working_u8: &'a mut [u8],
+    iters: usize,
+    decoded: bool,
+}
+
+impl<'a, T: 'a + DecodeFrom> MsDecoder<'a, T> {
+    /// Create a new resumable decoder for `code`.
+    ///
+    /// The arguments have the same requirements as for `decode_ms`. The buffers stay borrowed
+    /// until the decoder is dropped, and must not be modified in between calls to `run`.
+    pub fn new(code: LDPCCode, llrs: &'a [T], output: &'a mut [u8], working: &'a mut [T],
+               working_u8: &'a mut [u8]) -> MsDecoder<'a, T>
+    {
+        assert_eq!(llrs.len(), code.n(), "llrs.len() != n");
+        assert_eq!(output.len(), code.output_len(), "output.len() != (n+p)/8");
+        assert_eq!(working.len(), code.decode_ms_working_len(), "working.len() incorrect");
+        assert_eq!(working_u8.len(), code.decode_ms_working_u8_len(),
+                   "working_u8 != (n+p-k)/8");
+        MsDecoder { code, llrs, output, working, working_u8, iters: 0, decoded: false }
+    }
+
+    /// Run at most `iters` more iterations, stopping early if a codeword is found.
+    ///
+    /// Returns true once a codeword has been found, after which further calls do nothing.
+    pub fn run(&mut self, iters: usize) -> bool {
+        if self.decoded {
+            return true;
+        }
+        let (success, total) = self.code.decode_ms_core(
+            self.llrs, self.output, self.working, self.working_u8, self.iters,
+            self.iters + iters, |_| true, |_, _| false);
+        self.iters = total;
+        self.decoded = success;
+        success
+    }
+
+    /// Get the number of iterations run so far.
+    ///
+    /// Once a codeword is found this is the value `decode_ms` would have returned.
+    pub fn iters(&self) -> usize {
+        self.iters
+    }
+
+    /// Check whether a codeword has been found.
+    pub fn is_decoded(&self) -> bool {
+        self.decoded
+    }
+
+    /// Get the decoded codeword if `is_decoded()`, or otherwise the current hard decisions.
+    pub fn output(&self) -> &[u8] {
+        self.output
+    }
+
+    /// Get the current marginal LLRs for all n + punctured_bits bits.
+    pub fn marginals(&self) -> &[T] {
+        self.code.ms_marginals(self.working)
+    }
+}
+
+/// Soft confidence in a frame decoded by `decode_ms`, from the final marginal LLRs of the
+/// k information bits.
+///
+/// A frame which satisfied every parity check but has a low `min_llr` or a high
+/// `bit_error_probability` only just converged, and is more likely than usual to be an
+/// undetected error.
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub struct Confidence {
+    /// Smallest |LLR| of any information bit.
+    pub min_llr: f32,
+
+    /// Mean |LLR| of the information bits.
+    pub mean_llr: f32,
+
+    /// Estimated expected fraction of information bits in error, the mean over all
+    /// information bits of 1/(1 + exp(|LLR|)).
+    pub bit_error_probability: f32,
+}
 
 impl LDPCCode {
 
@@ -144,8 +379,13 @@ impl LDPCCode {
     /// Returns `(success, number of iterations run)`. Success only indicates that every punctured
     /// bit got a majority vote; but they might still be wrong; likewise failure means not every
     /// bit got a vote but many may still have been determined correctly.
-    fn decode_erasures(&self, codeword: &mut [u8], working: &mut [u8], maxiters: usize)
+    ///
+    /// `deadline` is polled after every iteration that does not finish, and decoding stops
+    /// early once it returns true.
+    fn decode_erasures<F>(&self, codeword: &mut [u8], working: &mut [u8], maxiters: usize,
+                          deadline: &mut F)
         -> (bool, usize)
+        where F: FnMut() -> bool
     {
         assert_eq!(codeword.len(), self.output_len());
         assert_eq!(working.len(), self.decode_bf_working_len());
@@ -219,6 +459,10 @@ impl LDPCCode {
                 // Hurray we're done
                 return (true, iter)
             }
+
+            if deadline() {
+                return (false, iter + 1)
+            }
         }
 
         // If we finished the iteration loop then we did not succeed.
@@ -252,14 +496,74 @@ impl LDPCCode {
         assert_eq!(working.len(), self.decode_bf_working_len(), "working.len() incorrect");
 
         output[..self.n()/8].copy_from_slice(input);
+        self.decode_bf_inplace(output, working, maxiters)
+    }
+
+    /// Bit flipping decoder with a deadline.
+    ///
+    /// This is the same as `decode_bf`, except that `deadline` is called after every iteration
+    /// (of both the erasure and bit flipping stages) which does not finish decoding, and
+    /// decoding stops as soon as it returns true. Use it to check a hardware timer or cycle
+    /// counter so decoding always finishes within a time budget; see `decode_bf_edge_visits`
+    /// for a bound on the work done between calls.
+    ///
+    /// Returns `(decoding success, iters)`, where `iters` counts the iterations run before
+    /// any deadline.
+    pub fn decode_bf_deadline<F>(&self, input: &[u8], output: &mut [u8], working: &mut [u8],
+                                 maxiters: usize, deadline: F)
+        -> (bool, usize)
+        where F: FnMut() -> bool
+    {
+        assert_eq!(input.len(), self.n()/8, "input.len() != n/8");
+        assert_eq!(output.len(), self.output_len(), "output.len != (n+p)/8");
+        assert_eq!(working.len(), self.decode_bf_working_len(), "working.len() incorrect");
+
+        output[..self.n()/8].copy_from_slice(input);
+        self.decode_bf_core(output, working, maxiters, deadline)
+    }
+
+    /// Get the worst-case number of parity check edge visits made by `decode_bf` with
+    /// `maxiters`.
+    ///
+    /// Each bit flipping iteration walks every edge twice, as does each erasure decoding
+    /// iteration for punctured codes, so this is 2 * paritycheck_sum * maxiters for the
+    /// unpunctured TC codes and twice that for the TM codes. The run time of each iteration
+    /// is dominated by these visits.
+    pub fn decode_bf_edge_visits(&self, maxiters: usize) -> usize {
+        let stages = if self.punctured_bits() > 0 { 2 } else { 1 };
+        stages * 2 * self.paritycheck_sum() as usize * maxiters
+    }
+
+    /// Run the bit flipping decoder on `output`, which must already have its first n bits set
+    /// to the received hard information.
+    pub(crate) fn decode_bf_inplace(&self, output: &mut [u8], working: &mut [u8],
+                                    maxiters: usize)
+        -> (bool, usize)
+    {
+        self.decode_bf_core(output, working, maxiters, || false)
+    }
 
+    /// Run the bit flipping decoder as for `decode_bf_inplace`, but stop early once
+    /// `deadline`, which is polled after every iteration that does not finish, returns true.
+    fn decode_bf_core<F>(&self, output: &mut [u8], working: &mut [u8], maxiters: usize,
+                         mut deadline: F)
+        -> (bool, usize)
+        where F: FnMut() -> bool
+    {
         // For punctured codes we must first try and fix all the punctured bits.
         // We run them through an erasure decoding algorithm and record how many iterations
         // it took (so we can return the total).
+        let mut expired = false;
         let erasure_iters = if self.punctured_bits() > 0 {
-            let (_, iters) = self.decode_erasures(output, working, maxiters);
+            let (_, iters) = self.decode_erasures(output, working, maxiters, &mut || {
+                expired = deadline();
+                expired
+            });
             iters
         } else { 0 };
+        if expired {
+            return (false, erasure_iters);
+        }
 
         // Working area: we use the top bit of the first k bytes to store that parity check,
         // and the remaining 7 bits of the first n+p bytes to store violation count for that var.
@@ -298,11 +602,40 @@ impl LDPCCode {
                     }
                 }
             }
+
+            if deadline() {
+                return (false, iter + 1 + erasure_iters);
+            }
         }
 
         (false, maxiters + erasure_iters)
     }
 
+    /// Bit flipping decoder for input held in an arbitrary bit slice.
+    ///
+    /// This is the same as `decode_bf` except `input` is a view of n bits which may start at any
+    /// bit offset inside a larger buffer and use either bit ordering. The input bits are copied
+    /// into `output` (which must still be `output_len()` bytes) before decoding, so no other
+    /// temporary buffer is required. Use `BitSliceMut::copy_from_bytes` if you need to write
+    /// the decoded bits back into an unaligned position.
+    pub fn decode_bf_bits(&self, input: &BitSlice, output: &mut [u8],
+                          working: &mut [u8], maxiters: usize)
+        -> (bool, usize)
+    {
+        assert_eq!(input.len(), self.n(), "input.len() != n");
+        assert_eq!(output.len(), self.output_len(), "output.len != (n+p)/8");
+        assert_eq!(working.len(), self.decode_bf_working_len(), "working.len() incorrect");
+
+        for o in &mut output[..] { *o = 0 }
+        for i in 0..self.n() {
+            if input.get(i) {
+                output[i/8] |= 1 << (7 - (i%8));
+            }
+        }
+
+        self.decode_bf_inplace(output, working, maxiters)
+    }
+
     /// Message passing based min-sum decoder.
     ///
     /// This algorithm is slower and requires more memory than the bit-flipping decode, but
@@ -350,6 +683,26 @@ impl LDPCCode {
                                     working: &mut [T], working_u8: &mut [u8],
                                     maxiters: usize)
         -> (bool, usize)
+    {
+        self.decode_ms_core(llrs, output, working, working_u8, 0, maxiters,
+                            |_| true, |_, _| false)
+    }
+
+    /// Min-sum decoder core shared by `decode_ms` and its variants.
+    ///
+    /// Whenever all parity checks are satisfied, the hard decision is written to `output` and
+    /// passed to `accept`. If `accept` returns true decoding stops successfully, otherwise
+    /// iterations continue as though the codeword had not been found.
+    ///
+    /// After every iteration that does not find a codeword, `stop` is called with the packed
+    /// parity check results and the current marginals. If it returns true decoding stops
+    /// unsuccessfully, returning the number of iterations run.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn decode_ms_core<T, F, G>(
+        &self, llrs: &[T], output: &mut [u8], working: &mut [T], working_u8: &mut [u8],
+        first_iter: usize, maxiters: usize, mut accept: F, mut stop: G)
+        -> (bool, usize)
+        where T: DecodeFrom, F: FnMut(&[u8]) -> bool, G: FnMut(&[u8], &[T]) -> bool
     {
         let n = self.n();
         let k = self.k();
@@ -366,14 +719,18 @@ impl LDPCCode {
         // Rename working_u8 to ui_sgns, we'll use it to accumulate signs for each check
         let ui_sgns = working_u8;
 
-        // Zero the working area and split it up
-        for w in &mut working[..] { *w = T::zero() }
+        // Zero the working area when starting afresh, and split it up. When resuming, the
+        // messages left by the previous iteration carry on from where they were.
+        if first_iter == 0 {
+            for w in &mut working[..] { *w = T::zero() }
+        }
         let (u, working)        = working.split_at_mut(self.paritycheck_sum() as usize);
         let (v, working)        = working.split_at_mut(self.paritycheck_sum() as usize);
         let (va, working)       = working.split_at_mut(n + p);
         let (ui_min1, ui_min2)  = working.split_at_mut(n + p - k);
 
-        for iter in 0..maxiters {
+        let mut iters = maxiters;
+        for iter in first_iter..maxiters {
             // Initialise the marginals to the input LLRs (and to 0 for punctured bits)
             va[..llrs.len()].copy_from_slice(llrs);
             for x in &mut va[llrs.len()..] { *x = T::zero() }
@@ -439,15 +796,20 @@ impl LDPCCode {
 
             // Check parities. If none are 1 then we have a valid codeword.
             if *parities.iter().max().unwrap() == 0 {
-                // Hard decode marginals into the output
-                let output = parities;
-                for o in &mut output[..] { *o = 0 }
+                // Hard decode marginals into the output. If the caller rejects this codeword,
+                // the parities are recomputed from scratch on the next iteration anyway.
+                for o in &mut parities[..] { *o = 0 }
                 for (var, &va) in va[0..(n+p)].iter().enumerate() {
                     if va <= T::zero() {
-                        output[var/8] |= 1 << (7 - (var%8));
+                        parities[var/8] |= 1 << (7 - (var%8));
                     }
                 }
-                return (true, iter);
+                if accept(parities) {
+                    return (true, iter);
+                }
+            } else if stop(parities, &va[0..(n+p)]) {
+                iters = iter + 1;
+                break;
             }
         }
 
@@ -459,7 +821,184 @@ impl LDPCCode {
                 output[var/8] |= 1 << (7 - (var%8));
             }
         }
-        (false, maxiters)
+        (false, iters)
+    }
+
+    /// Run the min-sum decoder as for `decode_ms`, and also compute extrinsic information.
+    ///
+    /// `extrinsic` must have n elements, and is written with the final marginal LLR of each
+    /// transmitted bit minus its input LLR: the information the code contributed about each
+    /// bit, which can be fed back to a soft demapper as prior information.
+    ///
+    /// All other arguments and the return value are as for `decode_ms`.
+    pub fn decode_ms_extrinsic<T: DecodeFrom>(&self, llrs: &[T], output: &mut [u8],
+                                              working: &mut [T], working_u8: &mut [u8],
+                                              maxiters: usize, extrinsic: &mut [T])
+        -> (bool, usize)
+    {
+        assert_eq!(extrinsic.len(), self.n(), "extrinsic.len() != n");
+        let result = self.decode_ms(llrs, output, working, working_u8, maxiters);
+        let marginals = self.ms_marginals(working);
+        for ((e, &va), &llr) in extrinsic.iter_mut().zip(marginals.iter()).zip(llrs.iter()) {
+            *e = va.saturating_add(-llr);
+        }
+        result
+    }
+
+    /// Run the min-sum decoder as for `decode_ms`, but give up early on frames which are
+    /// unlikely to decode according to `early`.
+    ///
+    /// All other arguments are as for `decode_ms`. Returns the reason decoding stopped and the
+    /// number of iterations run; decoding succeeded only if the reason is
+    /// `StopReason::Codeword`, in which case the iteration count is as for `decode_ms`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn decode_ms_early<T: FloatConvert>(&self, llrs: &[T], output: &mut [u8],
+                                          working: &mut [T], working_u8: &mut [u8],
+                                          maxiters: usize, early: &EarlyStopping)
+        -> (StopReason, usize)
+    {
+        let mut reason = StopReason::MaxIters;
+        let mut last_hash = 0;
+        let mut stable = 0;
+        let mut best_unsatisfied = usize::MAX;
+        let mut stalled = 0;
+
+        let (success, iters) = self.decode_ms_core(llrs, output, working, working_u8, 0, maxiters,
+                                                   |_| true, |parities, marginals| {
+            if early.stable_iters > 0 {
+                // Track the hard decisions by an FNV-1a hash of their packed bits
+                let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+                for chunk in marginals.chunks(64) {
+                    let mut word = 0u64;
+                    for va in chunk {
+                        word = (word << 1) | (*va <= T::zero()) as u64;
+                    }
+                    hash = (hash ^ word).wrapping_mul(0x0100_0000_01B3);
+                }
+                if hash == last_hash {
+                    stable += 1;
+                    if stable >= early.stable_iters {
+                        reason = StopReason::Stable;
+                        return true;
+                    }
+                } else {
+                    stable = 0;
+                    last_hash = hash;
+                }
+            }
+
+            if early.stall_iters > 0 {
+                let unsatisfied = parities.iter().map(|x| x.count_ones() as usize).sum();
+                if unsatisfied < best_unsatisfied {
+                    best_unsatisfied = unsatisfied;
+                    stalled = 0;
+                } else {
+                    stalled += 1;
+                    if stalled >= early.stall_iters {
+                        reason = StopReason::Stalled;
+                        return true;
+                    }
+                }
+            }
+
+            if early.saturation_fraction > 0.0 {
+                let saturated = marginals.iter()
+                                         .filter(|va| va.abs().to_f32() >= early.saturation_level)
+                                         .count();
+                if saturated as f32 >= early.saturation_fraction * marginals.len() as f32 {
+                    reason = StopReason::Saturated;
+                    return true;
+                }
+            }
+
+            false
+        });
+
+        if success {
+            (StopReason::Codeword, iters)
+        } else {
+            (reason, iters)
+        }
+    }
+
+    /// Min-sum decoder with a deadline.
+    ///
+    /// This is the same as `decode_ms`, except that `deadline` is called after every iteration
+    /// which does not find a codeword, and decoding stops as soon as it returns true. Use it to
+    /// check a hardware timer or cycle counter so decoding always finishes within a time
+    /// budget; see `decode_ms_edge_visits` for a bound on the work done between calls.
+    ///
+    /// Returns `(decoding success, iters)`, where `iters` counts the iterations run before
+    /// any deadline, and `output` holds the hard decisions if decoding stopped early.
+    #[allow(clippy::too_many_arguments)]
+    pub fn decode_ms_deadline<T, F>(&self, llrs: &[T], output: &mut [u8], working: &mut [T],
+                                    working_u8: &mut [u8], maxiters: usize, mut deadline: F)
+        -> (bool, usize)
+        where T: DecodeFrom, F: FnMut() -> bool
+    {
+        self.decode_ms_core(llrs, output, working, working_u8, 0, maxiters,
+                            |_| true, |_, _| deadline())
+    }
+
+    /// Get the worst-case number of parity check edge visits made by `decode_ms` with
+    /// `maxiters`.
+    ///
+    /// Each iteration walks every edge twice, once to update the messages to the variables
+    /// and once to update the messages to the checks, so this is 2 * paritycheck_sum *
+    /// maxiters. Each iteration also makes a few passes over the n + punctured_bits
+    /// marginals and n + punctured_bits - k checks, but the edge visits dominate.
+    pub fn decode_ms_edge_visits(&self, maxiters: usize) -> usize {
+        2 * self.paritycheck_sum() as usize * maxiters
+    }
+
+    /// Get the marginal LLRs for all n + punctured_bits bits from the `working` area of a
+    /// completed `decode_ms` call.
+    ///
+    /// These are the decoder's final soft decisions, and remain meaningful when decoding
+    /// failed, for example as reliabilities for `decode_osd`.
+    pub fn ms_marginals<'a, T: DecodeFrom>(&self, working: &'a [T]) -> &'a [T] {
+        let start = 2 * self.paritycheck_sum() as usize;
+        &working[start..start + self.n() + self.punctured_bits()]
+    }
+
+    /// Compute the `Confidence` of the information bits from the `working` area of a completed
+    /// `decode_ms` call.
+    ///
+    /// `scale` is the factor the LLRs given to `decode_ms` were multiplied by (for example
+    /// the `Quantiser` scale), so that LLRs are converted back to natural units before
+    /// estimating probabilities. Use 1.0 for unscaled floating point LLRs.
+    pub fn ms_confidence<T: FloatConvert>(&self, working: &[T], scale: f32) -> Confidence {
+        let k = self.k();
+        let mut min_llr = f32::INFINITY;
+        let mut sum_llr = 0.0;
+        let mut sum_p = 0.0;
+        for va in &self.ms_marginals(working)[..k] {
+            let llr = va.abs().to_f32() / scale;
+            if llr < min_llr {
+                min_llr = llr;
+            }
+            sum_llr += llr;
+            sum_p += 1.0 / (1.0 + expf(llr));
+        }
+        Confidence {
+            min_llr,
+            mean_llr: sum_llr / k as f32,
+            bit_error_probability: sum_p / k as f32,
+        }
+    }
+
+    /// Run `decode_ms` and also compute the `Confidence` of the decoded frame.
+    ///
+    /// Arguments are as for `decode_ms`, with `scale` as for `ms_confidence`. Returns
+    /// `(decoding success, iters, confidence)`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn decode_ms_confidence<T: FloatConvert>(&self, llrs: &[T], output: &mut [u8],
+                                               working: &mut [T], working_u8: &mut [u8],
+                                               maxiters: usize, scale: f32)
+        -> (bool, usize, Confidence)
+    {
+        let (success, iters) = self.decode_ms(llrs, output, working, working_u8, maxiters);
+        (success, iters, self.ms_confidence(working, scale))
     }
 
     /// Convert hard information into LLRs.
@@ -480,6 +1019,32 @@ impl LDPCCode {
         }
     }
 
+    /// Convert hard information held in an arbitrary bit slice into LLRs.
+    ///
+    /// This is the same as `hard_to_llrs` except `input` is a view of n bits which may start
+    /// at any bit offset inside a larger buffer and use either bit ordering.
+    pub fn hard_bits_to_llrs<T: DecodeFrom>(&self, input: &BitSlice, llrs: &mut [T]) {
+        assert_eq!(input.len(), self.n(), "input.len() != n");
+        assert_eq!(llrs.len(), self.n(), "llrs.len() != n");
+        let llr = -T::one();
+        for (i, x) in llrs.iter_mut().enumerate() {
+            *x = if input.get(i) { llr } else { -llr };
+        }
+    }
+
+    /// Convert LLRs into hard information held in an arbitrary bit slice.
+    ///
+    /// This is the same as `llrs_to_hard` except `output` is a view of n bits which may start
+    /// at any bit offset inside a larger buffer and use either bit ordering. Bits outside the
+    /// view are left untouched.
+    pub fn llrs_to_hard_bits<T: DecodeFrom>(&self, llrs: &[T], output: &mut BitSliceMut) {
+        assert_eq!(llrs.len(), self.n(), "llrs.len() != n");
+        assert_eq!(output.len(), self.n(), "output.len() != n");
+        for (i, llr) in llrs.iter().enumerate() {
+            output.set(i, *llr < T::zero());
+        }
+    }
+
     /// Convert LLRs into hard information.
     ///
     /// `llrs` must be n long, `output` must be n/8 long.
@@ -501,10 +1066,12 @@ impl LDPCCode {
 mod tests {
     use std::prelude::v1::*;
 
+    use ::bits::{BitOrder, BitSlice, BitSliceMut};
     use ::codes::{LDPCCode, CodeParams,
                   TC128_PARAMS,  TC256_PARAMS,  TC512_PARAMS,
                   TM1280_PARAMS, TM1536_PARAMS, TM2048_PARAMS,
                   TM5120_PARAMS, TM6144_PARAMS, TM8192_PARAMS};
+    use super::{EarlyStopping, StopReason, MsDecoder};
 
     const CODES: [LDPCCode;  9] = [LDPCCode::TC128,   LDPCCode::TC256,   LDPCCode::TC512,
                                    LDPCCode::TM1280,  LDPCCode::TM1536,  LDPCCode::TM2048,
@@ -613,7 +1180,8 @@ mod tests {
             output[..txcode.len()].copy_from_slice(&txcode);
 
             // Run erasure decoder
-            let (success, _) = code.decode_erasures(&mut output, &mut working, 50);
+            let (success, _) = code.decode_erasures(&mut output, &mut working, 50,
+                                                    &mut || false);
 
             assert!(success);
 
@@ -685,4 +1253,276 @@ mod tests {
             assert_eq!(&txcode[..], &output[..txcode.len()]);
         }
     }
+
+    #[test]
+    fn test_decode_ms_extrinsic() {
+        for code in &CODES {
+            let txdata: Vec<u8> = (0..code.k()/8).map(|x| x as u8).collect();
+            let mut txcode = vec![0u8; code.n()/8];
+            code.copy_encode(&txdata, &mut txcode);
+            let mut rxcode = txcode.clone();
+            rxcode[0] ^= 1<<7 | 1<<5 | 1<<3;
+            let mut llrs = vec![0i16; code.n()];
+            code.hard_to_llrs(&rxcode, &mut llrs);
+
+            let mut working = vec![0i16; code.decode_ms_working_len()];
+            let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+            let mut output = vec![0u8; code.output_len()];
+            let mut extrinsic = vec![0i16; code.n()];
+            let (success, _) = code.decode_ms_extrinsic(&llrs, &mut output, &mut working,
+                                                        &mut working_u8, 50, &mut extrinsic);
+            assert!(success);
+            assert_eq!(&txcode[..], &output[..txcode.len()]);
+
+            // Input plus extrinsic gives the marginals, which agree with the output
+            for i in 0..code.n() {
+                let bit = output[i/8] >> (7 - (i%8)) & 1;
+                assert_eq!(llrs[i] + extrinsic[i] <= 0, bit == 1);
+            }
+
+            // The corrupted bits were corrected by the code overruling the channel
+            for &i in &[0, 2, 4] {
+                assert!((extrinsic[i] > 0) != (llrs[i] > 0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_ms_confidence() {
+        let code = LDPCCode::TC256;
+        let txdata: Vec<u8> = (0..code.k()/8).map(|x| x as u8).collect();
+        let mut txcode = vec![0u8; code.n()/8];
+        code.copy_encode(&txdata, &mut txcode);
+        let mut llrs = vec![0f32; code.n()];
+        code.hard_to_llrs(&txcode, &mut llrs);
+        for x in llrs.iter_mut() { *x *= 4.0 }
+
+        let mut working = vec![0f32; code.decode_ms_working_len()];
+        let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+        let mut output = vec![0u8; code.output_len()];
+        let (success, _, clean) = code.decode_ms_confidence(&llrs, &mut output, &mut working,
+                                                            &mut working_u8, 50, 1.0);
+        assert!(success);
+        assert_eq!((clean.min_llr, clean.mean_llr), (4.0, 4.0));
+        assert!((clean.bit_error_probability - 0.018).abs() < 0.001);
+
+        // Halving the scale halves every LLR
+        let half = code.ms_confidence(&working, 2.0);
+        assert_eq!(half.min_llr, clean.min_llr / 2.0);
+
+        // A frame with several weak errors still decodes, with more confidence after the
+        // decoder has run some iterations
+        for i in 0..8 {
+            llrs[i * 17] *= -0.1;
+        }
+        let (success, iters, noisy) = code.decode_ms_confidence(&llrs, &mut output, &mut working,
+                                                                &mut working_u8, 50, 1.0);
+        assert!(success && iters > 0);
+        assert_eq!(&output[..txcode.len()], &txcode[..]);
+        assert!(noisy.bit_error_probability < clean.bit_error_probability);
+
+        // Noise does not decode, and gives low confidence
+        let noise: Vec<f32> = (0..code.n()).map(|i| ((i * 7919) % 11) as f32 / 5.0 - 1.0)
+                                           .collect();
+        let (success, _, fail) = code.decode_ms_confidence(&noise, &mut output, &mut working,
+                                                           &mut working_u8, 50, 1.0);
+        assert!(!success);
+        assert!(fail.min_llr < 1.0);
+        assert!(fail.bit_error_probability > clean.bit_error_probability);
+    }
+
+    #[test]
+    fn test_ms_decoder_resume() {
+        for code in &CODES {
+            let txdata: Vec<u8> = (0..code.k()/8).map(|x| (x * 3) as u8).collect();
+            let mut txcode = vec![0u8; code.n()/8];
+            code.copy_encode(&txdata, &mut txcode);
+            let mut llrs = vec![0i16; code.n()];
+            code.hard_to_llrs(&txcode, &mut llrs);
+            for x in llrs.iter_mut() { *x *= 8 }
+            for i in 0..code.n()/16 {
+                llrs[i * 16 + 3] = -llrs[i * 16 + 3] / 4;
+            }
+
+            // Both a frame which decodes and one which cannot (with too few iterations)
+            for &maxiters in &[50, 2] {
+                let mut working = vec![0i16; code.decode_ms_working_len()];
+                let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+                let mut output = vec![0u8; code.output_len()];
+                let (success, iters) = code.decode_ms(&llrs, &mut output, &mut working,
+                                                      &mut working_u8, maxiters);
+
+                let mut working2 = vec![0i16; code.decode_ms_working_len()];
+                let mut working_u8_2 = vec![0u8; code.decode_ms_working_u8_len()];
+                let mut output2 = vec![0u8; code.output_len()];
+                {
+                    let mut decoder = MsDecoder::new(*code, &llrs, &mut output2,
+                                                     &mut working2, &mut working_u8_2);
+                    while !decoder.run(1) && decoder.iters() < maxiters {}
+                    assert_eq!((decoder.is_decoded(), decoder.iters()), (success, iters));
+                    assert_eq!(decoder.marginals(), code.ms_marginals(&working));
+                    if success {
+                        // Further calls once decoded change nothing
+                        assert!(decoder.run(10));
+                        assert_eq!(decoder.iters(), iters);
+                    }
+                }
+                assert_eq!(output, output2);
+                assert_eq!(working, working2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_deadline() {
+        for (code, param) in CODES.iter().zip(PARAMS.iter()) {
+            // The worst case edge visits follow from the parity check matrix size
+            assert_eq!(code.iter_paritychecks().count(), param.paritycheck_sum as usize);
+            assert_eq!(code.decode_ms_edge_visits(10), 20 * param.paritycheck_sum as usize);
+            let stages = if param.punctured_bits > 0 { 2 } else { 1 };
+            assert_eq!(code.decode_bf_edge_visits(10),
+                       stages * 20 * param.paritycheck_sum as usize);
+
+            // Noise never decodes, so stops after exactly as many iterations as the deadline
+            let noise: Vec<i8> = (0..code.n()).map(|i| ((i * 7919) % 7) as i8 - 3).collect();
+            let mut working = vec![0i8; code.decode_ms_working_len()];
+            let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+            let mut output = vec![0u8; code.output_len()];
+            let mut polls = 0;
+            let result = code.decode_ms_deadline(&noise, &mut output, &mut working,
+                                                 &mut working_u8, 100, || {
+                polls += 1;
+                polls == 3
+            });
+            assert_eq!(result, (false, 3));
+
+            let mut hard = vec![0u8; code.n()/8];
+            code.llrs_to_hard(&noise, &mut hard);
+            let mut working = vec![0u8; code.decode_bf_working_len()];
+            let mut polls = 0;
+            let result = code.decode_bf_deadline(&hard, &mut output, &mut working, 100, || {
+                polls += 1;
+                polls == 3
+            });
+            assert_eq!(result, (false, 3));
+
+            // A deadline which never passes gives the same result as decoding without one
+            let txdata: Vec<u8> = (0..code.k()/8).map(|x| x as u8).collect();
+            let mut txcode = vec![0u8; code.n()/8];
+            code.copy_encode(&txdata, &mut txcode);
+            txcode[1] ^= 0x10;
+            let expected = code.decode_bf(&txcode, &mut output, &mut working, 20);
+            assert!(expected.0);
+            assert_eq!(code.decode_bf_deadline(&txcode, &mut output, &mut working, 20,
+                                               || false), expected);
+            assert_eq!(&output[..code.k()/8], &txdata[..]);
+        }
+    }
+
+    #[test]
+    fn test_decode_ms_early() {
+        let code = LDPCCode::TM1280;
+        let txdata: Vec<u8> = (0..code.k()/8).map(|x| x as u8).collect();
+        let mut txcode = vec![0u8; code.n()/8];
+        code.copy_encode(&txdata, &mut txcode);
+        let mut llrs = vec![0i16; code.n()];
+        code.hard_to_llrs(&txcode, &mut llrs);
+        for i in 0..code.n()/16 {
+            llrs[i * 16] = -llrs[i * 16] / 2;
+        }
+
+        let mut working = vec![0i16; code.decode_ms_working_len()];
+        let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+        let mut output = vec![0u8; code.output_len()];
+        let early = EarlyStopping { stable_iters: 4, stall_iters: 8, ..Default::default() };
+
+        // A decodable frame still decodes, in the same number of iterations
+        let (success, iters) = code.decode_ms(&llrs, &mut output, &mut working,
+                                              &mut working_u8, 100);
+        assert!(success);
+        let (reason, early_iters) = code.decode_ms_early(&llrs, &mut output, &mut working,
+                                                         &mut working_u8, 100, &early);
+        assert_eq!(reason, StopReason::Codeword);
+        assert_eq!(early_iters, iters);
+        assert_eq!(&output[..code.n()/8], &txcode[..]);
+
+        // Pure noise gives up well before maxiters, but runs to the end with no criteria
+        let mut x = 12345u32;
+        let noise: Vec<i16> = (0..code.n()).map(|_| {
+            x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            ((x >> 16) % 7) as i16 - 3
+        }).collect();
+        let (reason, iters) = code.decode_ms_early(&noise, &mut output, &mut working,
+                                                   &mut working_u8, 100, &early);
+        assert!(reason == StopReason::Stable || reason == StopReason::Stalled);
+        assert!(iters < 30);
+        let (reason, iters) = code.decode_ms_early(&noise, &mut output, &mut working,
+                                                   &mut working_u8, 100,
+                                                   &EarlyStopping::default());
+        assert_eq!((reason, iters), (StopReason::MaxIters, 100));
+
+        // With every eighth bit erased the frame takes many iterations to decode, and the
+        // marginals grow past every input LLR along the way, so saturation stops it first
+        let mut erased = vec![0i16; code.n()];
+        code.hard_to_llrs(&txcode, &mut erased);
+        for i in 0..code.n()/8 {
+            erased[i * 8] = 0;
+        }
+        let saturation = EarlyStopping { saturation_level: 2.0, saturation_fraction: 0.5,
+                                         ..Default::default() };
+        assert!(erased.iter().all(|x| (x.abs() as f32) < saturation.saturation_level));
+        let (reason, iters) = code.decode_ms_early(&erased, &mut output, &mut working,
+                                                   &mut working_u8, 100, &saturation);
+        assert_eq!(reason, StopReason::Saturated);
+        assert!(iters > 1 && iters < 20);
+    }
+
+    #[test]
+    fn test_decode_bits() {
+        for code in &CODES {
+            let txdata: Vec<u8> = (0..code.k()/8).map(|x| x as u8).collect();
+            let mut txcode = vec![0u8; code.n()/8];
+            code.copy_encode(&txdata, &mut txcode);
+
+            // Place the codeword LSB-first at an offset of 3 bits and corrupt some bits
+            let mut frame = vec![0u8; code.n()/8 + 1];
+            {
+                let mut rxbits = BitSliceMut::new(&mut frame, 3, code.n(), BitOrder::LsbFirst);
+                rxbits.copy_from_bytes(&txcode);
+                rxbits.toggle(0);
+                rxbits.toggle(2);
+                rxbits.toggle(4);
+            }
+            let rxbits = BitSlice::new(&frame, 3, code.n(), BitOrder::LsbFirst);
+
+            // Bit flipping decoder straight from the bit slice
+            let mut working = vec![0u8; code.decode_bf_working_len()];
+            let mut output = vec![0u8; code.output_len()];
+            let (success, _) = code.decode_bf_bits(&rxbits, &mut output, &mut working, 50);
+            assert!(success);
+            assert_eq!(&txcode[..], &output[..txcode.len()]);
+
+            // Min-sum decoder via LLRs
+            let mut llrs = vec![0i8; code.n()];
+            code.hard_bits_to_llrs(&rxbits, &mut llrs);
+            let mut working = vec![0i8; code.decode_ms_working_len()];
+            let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+            let (success, _) = code.decode_ms(&llrs, &mut output, &mut working,
+                                              &mut working_u8, 50);
+            assert!(success);
+            assert_eq!(&txcode[..], &output[..txcode.len()]);
+
+            // Convert LLRs back into hard bits in another unaligned buffer
+            let mut hard = vec![0xFFu8; code.n()/8 + 1];
+            {
+                let mut hardbits = BitSliceMut::new(&mut hard, 7, code.n(), BitOrder::MsbFirst);
+                code.llrs_to_hard_bits(&llrs, &mut hardbits);
+            }
+            let hardbits = BitSlice::new(&hard, 7, code.n(), BitOrder::MsbFirst);
+            for i in 0..code.n() {
+                assert_eq!(hardbits.get(i), rxbits.get(i));
+            }
+            assert_eq!(hard[0] & 0xFE, 0xFE);
+        }
+    }
 }
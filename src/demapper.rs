@@ -0,0 +1,433 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! This module provides soft demapping of complex I/Q samples into per-bit LLRs, for BPSK,
+//! QPSK, 8PSK, 16APSK and 32APSK.
+//!
+//! The constellations and their bit labelling follow DVB-S2 (EN 302 307). Every constellation
+//! is normalised to unit average symbol energy; for the APSK constellations the ring ratios
+//! are parameters, since they vary with code rate (for example DVB-S2 uses γ = 3.15 for rate
+//! 2/3 16APSK, and γ1 = 2.84, γ2 = 5.27 for rate 3/4 32APSK). Bits are mapped MSB first, the
+//! first bit of each group being the most significant bit of the constellation index.
+//!
+//! Given samples received with complex noise variance N0 (so N0/2 on each of I and Q), the
+//! LLR of each bit is computed either exactly (log-MAP), or with the cheaper max-log
+//! approximation which takes only the nearest constellation point with each bit value into
+//! account. LLRs are multiplied by a `scale` factor and written directly into the `llrs`
+//! buffer used by `decode_ms`, saturating if they don't fit in `T`.
+//!
+//! ```
+//! # use labrador_ldpc::LDPCCode;
+//! use labrador_ldpc::demapper::{Constellation, Demapping};
+//!
+//! let code = LDPCCode::TC256;
+//! let txdata: Vec<u8> = (0..code.k()/8).map(|i| i as u8).collect();
+//! let mut txcode = vec![0u8; code.n()/8];
+//! code.copy_encode(&txdata, &mut txcode);
+//!
+//! // 8PSK carries 3 bits per symbol, so a 256 bit codeword needs 86 symbols with padding.
+//! let c = Constellation::PSK8;
+//! let mut symbols = vec![(0.0, 0.0); c.symbols_for_bits(code.n())];
+//! c.map(&txcode, code.n(), &mut symbols);
+//!
+//! // ... transmit and receive the symbols ...
+//!
+//! let mut llrs = vec![0i16; code.n()];
+//! c.demap(Demapping::MaxLog, &symbols, 0.1, 8.0, &mut llrs);
+//!
+//! let mut working = vec![0i16; code.decode_ms_working_len()];
+//! let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+//! let mut rxdata = vec![0u8; code.output_len()];
+//! code.decode_ms(&llrs, &mut rxdata, &mut working, &mut working_u8, 20);
+//! assert_eq!(&rxdata[..code.k()/8], &txdata[..]);
+//! ```
+
+use ::decoder::FloatConvert;
+use ::math::{sqrtf, maxstar};
+
+use core::f32::consts::FRAC_1_SQRT_2 as R2;
+
+// cos and sin of pi/12 and pi/8
+const C12: f32 = 0.965_925_8;
+const S12: f32 = 0.258_819_04;
+const C8: f32 = 0.923_879_5;
+const S8: f32 = 0.382_683_43;
+
+/// 8PSK points by index.
+static PSK8: [(f32, f32); 8] = [
+    (R2, R2), (1.0, 0.0), (-1.0, 0.0), (-R2, -R2),
+    (0.0, 1.0), (R2, -R2), (-R2, R2), (0.0, -1.0),
+];
+
+/// 16APSK points by index, as (ring, I, Q) with ring 0 inner and 1 outer.
+static APSK16: [(u8, f32, f32); 16] = [
+    (1, R2, R2), (1, R2, -R2), (1, -R2, R2), (1, -R2, -R2),
+    (1, C12, S12), (1, C12, -S12), (1, -C12, S12), (1, -C12, -S12),
+    (1, S12, C12), (1, S12, -C12), (1, -S12, C12), (1, -S12, -C12),
+    (0, R2, R2), (0, R2, -R2), (0, -R2, R2), (0, -R2, -R2),
+];
+
+/// 32APSK points by index, as (ring, I, Q) with rings 0 inner, 1 middle and 2 outer.
+static APSK32: [(u8, f32, f32); 32] = [
+    (1, R2, R2), (1, S12, C12), (1, R2, -R2), (1, S12, -C12),
+    (1, -R2, R2), (1, -S12, C12), (1, -R2, -R2), (1, -S12, -C12),
+    (2, C8, S8), (2, S8, C8), (2, R2, -R2), (2, 0.0, -1.0),
+    (2, -R2, R2), (2, 0.0, 1.0), (2, -C8, -S8), (2, -S8, -C8),
+    (1, C12, S12), (0, R2, R2), (1, C12, -S12), (0, R2, -R2),
+    (1, -C12, S12), (0, -R2, R2), (1, -C12, -S12), (0, -R2, -R2),
+    (2, 1.0, 0.0), (2, R2, R2), (2, C8, -S8), (2, S8, -C8),
+    (2, -C8, S8), (2, -S8, C8), (2, -1.0, 0.0), (2, -R2, -R2),
+];
+
+/// Available constellations.
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum Constellation {
+    /// Binary phase shift keying, 0 → +1 and 1 → -1.
+    BPSK,
+
+    /// Gray-coded QPSK, with the first bit on I and the second on Q.
+    QPSK,
+
+    /// Gray-coded 8PSK.
+    PSK8,
+
+    /// 16APSK with 4 inner and 12 outer points, with ring ratio `gamma` = outer/inner.
+    APSK16 {
+        /// Ratio of outer to inner ring radius.
+        gamma: f32,
+    },
+
+    /// 32APSK with 4 inner, 12 middle and 16 outer points.
+    APSK32 {
+        /// Ratio of middle to inner ring radius.
+        gamma1: f32,
+
+        /// Ratio of outer to inner ring radius.
+        gamma2: f32,
+    },
+}
+
+/// LLR computation method.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub enum Demapping {
+    /// Exact log-MAP, summing the likelihoods of every constellation point.
+    LogMap,
+
+    /// Max-log approximation, using only the most likely point for each bit value.
+    MaxLog,
+}
+
+impl Constellation {
+    /// Get the number of bits carried by each symbol.
+    pub fn bits_per_symbol(&self) -> usize {
+        match *self {
+            Constellation::BPSK => 1,
+            Constellation::QPSK => 2,
+            Constellation::PSK8 => 3,
+            Constellation::APSK16 { .. } => 4,
+            Constellation::APSK32 { .. } => 5,
+        }
+    }
+
+    /// Get the number of points in the constellation.
+    pub fn size(&self) -> usize {
+        1 << self.bits_per_symbol()
+    }
+
+    /// Get the number of symbols needed to carry `bits` bits, rounding up.
+    pub fn symbols_for_bits(&self, bits: usize) -> usize {
+        bits.div_ceil(self.bits_per_symbol())
+    }
+
+    /// Get the ring radii, normalised to unit average energy.
+    fn radii(&self) -> [f32; 3] {
+        match *self {
+            Constellation::APSK16 { gamma } => {
+                let r1 = sqrtf(16.0 / (4.0 + 12.0 * gamma * gamma));
+                [r1, r1 * gamma, 0.0]
+            },
+            Constellation::APSK32 { gamma1, gamma2 } => {
+                let r1 = sqrtf(32.0 / (4.0 + 12.0 * gamma1 * gamma1 + 16.0 * gamma2 * gamma2));
+                [r1, r1 * gamma1, r1 * gamma2]
+            },
+            _ => [1.0, 1.0, 1.0],
+        }
+    }
+
+    /// Compute a point given precomputed ring radii.
+    fn point_with_radii(&self, index: usize, radii: &[f32; 3]) -> (f32, f32) {
+        let scaled = |p: &(u8, f32, f32)| (radii[p.0 as usize] * p.1, radii[p.0 as usize] * p.2);
+        match *self {
+            Constellation::BPSK => if index == 0 { (1.0, 0.0) } else { (-1.0, 0.0) },
+            Constellation::QPSK => {
+                let i = if index & 2 == 0 { R2 } else { -R2 };
+                let q = if index & 1 == 0 { R2 } else { -R2 };
+                (i, q)
+            },
+            Constellation::PSK8 => PSK8[index],
+            Constellation::APSK16 { .. } => scaled(&APSK16[index]),
+            Constellation::APSK32 { .. } => scaled(&APSK32[index]),
+        }
+    }
+
+    /// Get the (I, Q) coordinates of the point with the given index.
+    pub fn point(&self, index: usize) -> (f32, f32) {
+        assert!(index < self.size(), "index out of range");
+        self.point_with_radii(index, &self.radii())
+    }
+
+    /// Map the first `nbits` bits of `data` (MSB first) to `symbols`.
+    ///
+    /// `symbols` must be exactly `symbols_for_bits(nbits)` long. If `nbits` is not a multiple
+    /// of `bits_per_symbol()`, the final symbol is padded with zero bits.
+    pub fn map(&self, data: &[u8], nbits: usize, symbols: &mut [(f32, f32)]) {
+        assert!(data.len() * 8 >= nbits, "data shorter than nbits");
+        assert_eq!(symbols.len(), self.symbols_for_bits(nbits), "symbols.len() incorrect");
+        let bps = self.bits_per_symbol();
+        let radii = self.radii();
+        for (s, symbol) in symbols.iter_mut().enumerate() {
+            let mut index = 0;
+            for b in 0..bps {
+                let bit = s * bps + b;
+                index <<= 1;
+                if bit < nbits {
+                    index |= (data[bit/8] >> (7 - (bit%8)) & 1) as usize;
+                }
+            }
+            *symbol = self.point_with_radii(index, &radii);
+        }
+    }
+
+    /// Compute LLRs for each bit of each received sample in `samples`.
+    ///
+    /// * `noise_var` is the complex noise variance N0, and must be greater than zero
+    /// * `scale` multiplies every LLR before conversion to `T`
+    /// * `llrs` receives one LLR per bit, and `samples.len()` must equal
+    ///   `symbols_for_bits(llrs.len())`; LLRs for any padding bits in the final symbol are
+    ///   discarded
+    pub fn demap<T: FloatConvert>(&self, method: Demapping, samples: &[(f32, f32)],
+                                noise_var: f32, scale: f32, llrs: &mut [T])
+    {
+        self.demap_inner(method, samples, noise_var, scale, None, llrs);
+    }
+
+    /// Compute extrinsic LLRs for each bit given prior LLRs for every bit, for iterative
+    /// demapping and decoding.
+    ///
+    /// `priors` holds one LLR per bit in the same scaled units and order as `llrs`, for example
+    /// the extrinsic output of `decode_ms_extrinsic`. The prior information on the other bits of
+    /// each symbol sharpens the estimate of each bit, and the bit's own prior is removed from
+    /// the result so only new information is passed back to the decoder.
+    ///
+    /// All other arguments are as for `demap`, and with all-zero priors the result is the same.
+    pub fn demap_with_priors<T: FloatConvert>(&self, method: Demapping, samples: &[(f32, f32)],
+                                            noise_var: f32, scale: f32, priors: &[T],
+                                            llrs: &mut [T])
+    {
+        assert_eq!(priors.len(), llrs.len(), "priors.len() != llrs.len()");
+        self.demap_inner(method, samples, noise_var, scale, Some(priors), llrs);
+    }
+
+    fn demap_inner<T: FloatConvert>(&self, method: Demapping, samples: &[(f32, f32)],
+                                  noise_var: f32, scale: f32, priors: Option<&[T]>,
+                                  llrs: &mut [T])
+    {
+        let bps = self.bits_per_symbol();
+        assert!(noise_var > 0.0, "noise_var must be positive");
+        assert_eq!(samples.len(), self.symbols_for_bits(llrs.len()), "llrs.len() incorrect");
+
+        let radii = self.radii();
+        for (s, (sample, chunk)) in samples.iter().zip(llrs.chunks_mut(bps)).enumerate() {
+            let mut bitpriors = [0.0f32; 5];
+            if let Some(priors) = priors {
+                for (p, x) in bitpriors.iter_mut().zip(priors[s*bps..].iter().take(bps)) {
+                    *p = x.to_f32() / scale;
+                }
+            }
+            let mut bitllrs = [0.0f32; 5];
+            self.demap_symbol(method, *sample, noise_var, &radii, &bitpriors[..bps],
+                              &mut bitllrs[..bps]);
+            for (llr, x) in chunk.iter_mut().zip(bitllrs.iter()) {
+                *llr = T::from_f32(x * scale);
+            }
+        }
+    }
+
+    /// Compute the extrinsic LLRs for one sample into `out`, given `priors` for each bit.
+    fn demap_symbol(&self, method: Demapping, sample: (f32, f32), noise_var: f32,
+                    radii: &[f32; 3], priors: &[f32], out: &mut [f32])
+    {
+        let bps = out.len();
+        let mut acc0 = [f32::NEG_INFINITY; 5];
+        let mut acc1 = [f32::NEG_INFINITY; 5];
+        for index in 0..self.size() {
+            let (i, q) = self.point_with_radii(index, radii);
+            let di = sample.0 - i;
+            let dq = sample.1 - q;
+            let mut metric = -(di * di + dq * dq) / noise_var;
+
+            // Each bit's prior contributes +-L/2 to the log probability of this point
+            for (b, p) in priors.iter().enumerate() {
+                metric += if index >> (bps - 1 - b) & 1 == 0 { 0.5 * p } else { -0.5 * p };
+            }
+
+            for b in 0..bps {
+                let acc = if index >> (bps - 1 - b) & 1 == 0 { &mut acc0[b] } else { &mut acc1[b] };
+                *acc = match method {
+                    Demapping::LogMap => maxstar(*acc, metric),
+                    Demapping::MaxLog => if metric > *acc { metric } else { *acc },
+                };
+            }
+        }
+
+        // Remove each bit's own prior, which contributed exactly +-L/2 to every point in
+        // each half, leaving only the extrinsic information.
+        for b in 0..bps {
+            out[b] = acc0[b] - acc1[b] - priors[b];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+
+    use ::codes::LDPCCode;
+    use ::sim::Rng;
+    use super::{Constellation, Demapping};
+
+    fn constellations() -> Vec<Constellation> {
+        vec![Constellation::BPSK, Constellation::QPSK, Constellation::PSK8,
+             Constellation::APSK16 { gamma: 3.15 },
+             Constellation::APSK32 { gamma1: 2.84, gamma2: 5.27 }]
+    }
+
+    #[test]
+    fn test_constellations() {
+        for c in &constellations() {
+            // Unit average energy and all points distinct
+            let points: Vec<(f32, f32)> = (0..c.size()).map(|i| c.point(i)).collect();
+            let energy: f32 = points.iter().map(|p| p.0*p.0 + p.1*p.1).sum::<f32>()
+                              / c.size() as f32;
+            assert!((energy - 1.0).abs() < 1e-5);
+            for i in 0..points.len() {
+                for j in (i+1)..points.len() {
+                    let d = (points[i].0 - points[j].0).hypot(points[i].1 - points[j].1);
+                    assert!(d > 0.05);
+                }
+            }
+        }
+
+        // 8PSK is Gray coded: points pi/4 apart differ in one bit
+        let c = Constellation::PSK8;
+        for i in 0..8 {
+            for j in 0..8 {
+                let (a, b) = (c.point(i), c.point(j));
+                let d = (a.0 - b.0).hypot(a.1 - b.1);
+                if (d - 0.765_366_85).abs() < 1e-4 {
+                    assert_eq!((i ^ j).count_ones(), 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_demap_noiseless() {
+        let data: Vec<u8> = (0..15).map(|i| (i * 37 + 11) as u8).collect();
+        let nbits = data.len() * 8;
+        for c in &constellations() {
+            let mut symbols = vec![(0.0, 0.0); c.symbols_for_bits(nbits)];
+            c.map(&data, nbits, &mut symbols);
+            for method in &[Demapping::LogMap, Demapping::MaxLog] {
+                let mut llrs = vec![0.0f32; nbits];
+                c.demap(*method, &symbols, 0.1, 1.0, &mut llrs);
+                for (i, llr) in llrs.iter().enumerate() {
+                    let bit = data[i/8] >> (7 - (i%8)) & 1;
+                    assert_eq!(*llr > 0.0, bit == 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_demap_values() {
+        // For BPSK the exact LLR is 4y/N0
+        let mut llrs = [0.0f32; 2];
+        Constellation::BPSK.demap(Demapping::LogMap, &[(0.3, 0.5), (-1.2, 0.0)], 0.5, 1.0,
+                                  &mut llrs);
+        assert!((llrs[0] - 2.4).abs() < 1e-4);
+        assert!((llrs[1] + 9.6).abs() < 1e-4);
+
+        // Max-log is close to exact at high SNR, and never larger in magnitude at low SNR
+        let c = Constellation::APSK16 { gamma: 3.15 };
+        let sample = [(0.4, 0.2)];
+        let mut exact = [0.0f32; 4];
+        let mut approx = [0.0f32; 4];
+        c.demap(Demapping::LogMap, &sample, 0.01, 1.0, &mut exact);
+        c.demap(Demapping::MaxLog, &sample, 0.01, 1.0, &mut approx);
+        for (e, a) in exact.iter().zip(approx.iter()) {
+            assert!((e - a).abs() < 0.01 * e.abs() + 1e-3);
+        }
+
+        // Saturation into integer types
+        let mut llrs = [0i8; 3];
+        Constellation::PSK8.demap(Demapping::MaxLog, &[(1.0, 0.0)], 0.001, 1.0, &mut llrs);
+        assert_eq!(llrs, [127, 127, -127]);
+    }
+
+    #[test]
+    fn test_demap_priors() {
+        let c = Constellation::APSK16 { gamma: 3.15 };
+        let samples = [(0.3, 0.35), (-0.9, 0.1)];
+        let mut plain = [0.0f32; 8];
+        let mut with = [0.0f32; 8];
+        c.demap(Demapping::LogMap, &samples, 0.3, 1.0, &mut plain);
+        c.demap_with_priors(Demapping::LogMap, &samples, 0.3, 1.0, &[0.0; 8], &mut with);
+        assert_eq!(plain, with);
+
+        // Knowing the other bits of each symbol makes each bit's extrinsic LLR more confident
+        // in the correct direction, without including the bit's own prior.
+        let tx = [0b0000_1100u8];
+        let mut symbols = [(0.0, 0.0); 2];
+        c.map(&tx, 8, &mut symbols);
+        let noisy = [(symbols[0].0 + 0.2, symbols[0].1 - 0.1), (symbols[1].0 - 0.15, symbols[1].1)];
+        c.demap(Demapping::MaxLog, &noisy, 0.3, 1.0, &mut plain);
+        let priors: Vec<f32> = (0..8).map(|i| if tx[0] >> (7 - i) & 1 == 1 { -20.0 } else { 20.0 })
+                                     .collect();
+        c.demap_with_priors(Demapping::MaxLog, &noisy, 0.3, 1.0, &priors, &mut with);
+        for i in 0..8 {
+            assert!(with[i] * priors[i] > 0.0);
+            assert!(with[i].abs() >= plain[i].abs() - 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_demap_decode() {
+        let code = LDPCCode::TM2048;
+        let mut rng = Rng::new(3);
+        let mut txdata = vec![0u8; code.k()/8];
+        rng.fill_bytes(&mut txdata);
+        let mut txcode = vec![0u8; code.n()/8];
+        code.copy_encode(&txdata, &mut txcode);
+
+        for c in &constellations()[2..] {
+            let n0 = 0.02;
+            let mut symbols = vec![(0.0, 0.0); c.symbols_for_bits(code.n())];
+            c.map(&txcode, code.n(), &mut symbols);
+            for s in &mut symbols {
+                s.0 += ((n0 / 2.0f64).sqrt() * rng.gaussian()) as f32;
+                s.1 += ((n0 / 2.0f64).sqrt() * rng.gaussian()) as f32;
+            }
+
+            let mut llrs = vec![0.0f32; code.n()];
+            c.demap(Demapping::LogMap, &symbols, n0 as f32, 1.0, &mut llrs);
+            let mut working = vec![0.0f32; code.decode_ms_working_len()];
+            let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+            let mut rxdata = vec![0u8; code.output_len()];
+            let (success, _) = code.decode_ms(&llrs, &mut rxdata, &mut working,
+                                              &mut working_u8, 50);
+            assert!(success);
+            assert_eq!(&rxdata[..code.k()/8], &txdata[..]);
+        }
+    }
+}
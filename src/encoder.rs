@@ -4,7 +4,8 @@
 //! This module provides the encoding function for turning data into codewords.
 //!
 //! Please refer to the `encode` and `copy_encode` methods on
-//! [`LDPCCode`](../codes/enum.LDPCCode.html) for more details.
+//! [`LDPCCode`](../codes/enum.LDPCCode.html) for more details, or `encode_bits` and
+//! `copy_encode_bits` for codewords which are not byte-aligned.
 
 // We have a couple of expressions with +0 for clarity of where the 0 comes from
 #![cfg_attr(feature="cargo-clippy", allow(identity_op))]
@@ -12,6 +13,7 @@
 use core::slice;
 
 use ::codes::LDPCCode;
+use ::bits::{BitSlice, BitSliceMut};
 
 /// Trait for the types of codeword we can encode into.
 ///
@@ -253,8 +255,95 @@ impl EncodeInto for u64 {
     fn bitlength() -> usize { 64 }
 }
 
+/// Incremental encoder which accumulates parity bits as the message arrives.
+///
+/// Because every parity bit is a linear combination of message bits, the parity can be built up
+/// one byte at a time without ever holding the whole k-bit message in memory. Feed the message
+/// in with `feed` in chunks of any size; once k bits have been fed, `parity` returns the n-k
+/// parity bits to transmit after the message.
+///
+/// The caller provides the parity storage, which must be `(n-k)/8` (=`code.parity_len()`) bytes.
+///
+/// ```
+/// # use labrador_ldpc::LDPCCode;
+/// # use labrador_ldpc::encoder::StreamEncoder;
+/// let code = LDPCCode::TC128;
+/// let mut parity = [0u8; 8];
+/// let mut encoder = StreamEncoder::new(code, &mut parity);
+/// encoder.feed(&[0x00, 0x01, 0x02]);
+/// encoder.feed(&[0x03, 0x04, 0x05, 0x06, 0x07]);
+/// assert_eq!(encoder.parity().unwrap(), [0x34, 0x99, 0x98, 0x87, 0x94, 0xE1, 0x62, 0x56]);
+/// ```
+pub struct StreamEncoder<'a> {
+    code: LDPCCode,
+    parity: &'a mut [u8],
+    bytes_fed: usize,
+}
+
+impl<'a> StreamEncoder<'a> {
+    /// Create a new streaming encoder for `code`, using `parity` to accumulate the parity bits.
+    ///
+    /// `parity` must be `(n-k)/8` bytes long, and is zeroed.
+    pub fn new(code: LDPCCode, parity: &'a mut [u8]) -> StreamEncoder<'a> {
+        assert_eq!(parity.len(), code.parity_len(), "parity must be n-k bits long");
+        for x in parity.iter_mut() { *x = 0; }
+        StreamEncoder { code, parity, bytes_fed: 0 }
+    }
+
+    /// Feed the next chunk of message bytes into the encoder.
+    ///
+    /// Returns the number of bytes consumed from `data`, which is less than `data.len()` only
+    /// if the message is now complete (any further bytes belong to the next message).
+    pub fn feed(&mut self, data: &[u8]) -> usize {
+        let len = if data.len() > self.remaining() { self.remaining() } else { data.len() };
+        let parity = &mut *self.parity;
+        for (idx, byte) in data[..len].iter().enumerate() {
+            let bit0 = (self.bytes_fed + idx) * 8;
+            for i in 0..8 {
+                if byte >> (7 - i) & 1 == 1 {
+                    self.code.generator_parity_bits(bit0 + i, |j| parity[j/8] ^= 1 << (7 - (j%8)));
+                }
+            }
+        }
+        self.bytes_fed += len;
+        len
+    }
+
+    /// Number of message bytes still required before the parity is complete.
+    pub fn remaining(&self) -> usize {
+        self.code.k()/8 - self.bytes_fed
+    }
+
+    /// Returns true once all k message bits have been fed in.
+    pub fn is_complete(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Returns the finished `(n-k)/8` parity bytes, or `None` if the message is not yet complete.
+    pub fn parity(&self) -> Option<&[u8]> {
+        if self.is_complete() {
+            Some(&self.parity[..])
+        } else {
+            None
+        }
+    }
+
+    /// Reset the encoder to start accumulating a new message.
+    pub fn reset(&mut self) {
+        for x in self.parity.iter_mut() { *x = 0; }
+        self.bytes_fed = 0;
+    }
+}
+
 impl LDPCCode {
 
+    /// Get the length of [u8] required for the parity bits of a codeword.
+    ///
+    /// Equal to (n-k)/8.
+    pub fn parity_len(&self) -> usize {
+        (self.n() - self.k()) / 8
+    }
+
     /// Encode a codeword. This function assumes the first k bits of `codeword` have already
     /// been set to your data, and will set the remaining n-k bits appropriately.
     ///
@@ -288,6 +377,66 @@ impl LDPCCode {
         assert_eq!(codeword.len() * T::bitlength(), self.n(), "codeword must be n bits long");
         EncodeInto::copy_encode(self, data, codeword)
     }
+
+    /// Encode a codeword held in an arbitrary bit slice.
+    ///
+    /// This is the same as `encode` except `codeword` may start at any bit offset inside a
+    /// larger buffer and use either bit ordering. The first k bits of `codeword` must already
+    /// be set to your data, and the remaining n-k bits are overwritten with the parity bits.
+    ///
+    /// This works a bit at a time so is several times slower than `encode`, but avoids having
+    /// to shift the data into an aligned temporary buffer first.
+    pub fn encode_bits(&self, codeword: &mut BitSliceMut) {
+        assert_eq!(codeword.len(), self.n(), "codeword must be n bits long");
+        let k = self.k();
+
+        // Zero the parity bits
+        for j in k..self.n() {
+            codeword.set(j, false);
+        }
+
+        // For each set data bit, XOR in the corresponding row of the generator matrix
+        for bit in 0..k {
+            if codeword.get(bit) {
+                self.generator_parity_bits(bit, |j| codeword.toggle(k + j));
+            }
+        }
+    }
+
+    /// Encode a codeword held in an arbitrary bit slice, first copying in the data.
+    ///
+    /// This is the same as `encode_bits` except the k bits of `data` are copied into the
+    /// first part of `codeword` first.
+    pub fn copy_encode_bits(&self, data: &BitSlice, codeword: &mut BitSliceMut) {
+        assert_eq!(data.len(), self.k(), "data must be k bits long");
+        assert_eq!(codeword.len(), self.n(), "codeword must be n bits long");
+        codeword.slice_mut(0, self.k()).copy_from(data);
+        self.encode_bits(codeword);
+    }
+
+    /// Call `f(j)` for each parity bit `j` (in 0..n-k) set in row `bit` of the generator matrix.
+    ///
+    /// Row `bit` is found from the compact generator by taking row `bit/circulant_size` and
+    /// rotating each circulant block right by `bit%circulant_size`.
+    pub(crate) fn generator_parity_bits<F>(&self, bit: usize, mut f: F)
+        where F: FnMut(usize)
+    {
+        let r = self.n() - self.k();
+        let b = self.circulant_size();
+        let gc = self.compact_generator();
+        let row_len = r/64;
+        let (crow, offset) = (bit / b, bit % b);
+
+        for (idx, circ) in gc[crow*row_len..(crow+1)*row_len].iter().enumerate() {
+            let mut word = *circ;
+            while word != 0 {
+                let m = idx*64 + word.leading_zeros() as usize;
+                word &= !(1 << (63 - (m % 64)));
+                let block = m / b;
+                f(block*b + (m + offset) % b);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -295,6 +444,8 @@ mod tests {
     use std::prelude::v1::*;
 
     use ::codes::LDPCCode;
+    use ::bits::{BitOrder, BitSlice, BitSliceMut};
+    use super::StreamEncoder;
 
     macro_rules! test_encode {
         ($code:path, $parity:expr) => {
@@ -500,4 +651,75 @@ mod tests {
                       0x58, 0x77, 0x8C, 0x46, 0x31, 0x2B, 0x7E, 0xC1,
                       0xCB, 0x4A, 0x59, 0xFC, 0x44, 0xFA, 0xF8, 0x70]);
     }
+
+    #[test]
+    fn test_encode_bits() {
+        let codes = [LDPCCode::TC128, LDPCCode::TC512, LDPCCode::TM1280, LDPCCode::TM2048];
+        for code in &codes {
+            let txdata: Vec<u8> = (0..code.k()/8).map(|i| (i * 7) as u8).collect();
+            let mut txcode = vec![0u8; code.n()/8];
+            code.copy_encode(&txdata, &mut txcode);
+
+            // Encode MSB-first, 5 bits into a buffer, with the surrounding bits all set
+            let mut frame = vec![0xFFu8; code.n()/8 + 2];
+            {
+                let data = BitSlice::new(&txdata, 0, code.k(), BitOrder::MsbFirst);
+                let mut codeword = BitSliceMut::new(&mut frame, 5, code.n(), BitOrder::MsbFirst);
+                code.copy_encode_bits(&data, &mut codeword);
+            }
+            let codeword = BitSlice::new(&frame, 5, code.n(), BitOrder::MsbFirst);
+            let expected = BitSlice::new(&txcode, 0, code.n(), BitOrder::MsbFirst);
+            for i in 0..code.n() {
+                assert_eq!(codeword.get(i), expected.get(i));
+            }
+            assert_eq!(frame[0] & 0xF8, 0xF8);
+            assert_eq!(frame[code.n()/8 + 1] & 0x07, 0x07);
+
+            // Encode LSB-first at an offset of 11 bits, with the data already in place
+            let mut frame = vec![0u8; code.n()/8 + 2];
+            {
+                let mut codeword = BitSliceMut::new(&mut frame, 11, code.n(), BitOrder::LsbFirst);
+                for i in 0..code.k() {
+                    codeword.set(i, expected.get(i));
+                }
+                code.encode_bits(&mut codeword);
+            }
+            let codeword = BitSlice::new(&frame, 11, code.n(), BitOrder::LsbFirst);
+            for i in 0..code.n() {
+                assert_eq!(codeword.get(i), expected.get(i));
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream_encoder() {
+        let codes = [LDPCCode::TC128, LDPCCode::TC256, LDPCCode::TM1536, LDPCCode::TM5120];
+        for code in &codes {
+            let txdata: Vec<u8> = (0..code.k()/8).map(|i| (i * 3 + 1) as u8).collect();
+            let mut txcode = vec![0u8; code.n()/8];
+            code.copy_encode(&txdata, &mut txcode);
+
+            // Feed in awkwardly sized chunks, with trailing bytes from the next message
+            let mut stream = txdata.clone();
+            stream.extend_from_slice(&[0xAA, 0xBB]);
+            let mut parity = vec![0u8; code.parity_len()];
+            let mut encoder = StreamEncoder::new(*code, &mut parity);
+            let mut consumed = 0;
+            for chunk in stream.chunks(5) {
+                assert!(encoder.parity().is_none());
+                consumed += encoder.feed(chunk);
+                if encoder.is_complete() {
+                    break;
+                }
+            }
+            assert_eq!(consumed, code.k()/8);
+            assert_eq!(encoder.parity().unwrap(), &txcode[code.k()/8..]);
+
+            // Check reset lets us encode a second message
+            encoder.reset();
+            assert_eq!(encoder.remaining(), code.k()/8);
+            encoder.feed(&txdata);
+            assert_eq!(encoder.parity().unwrap(), &txcode[code.k()/8..]);
+        }
+    }
 }
@@ -0,0 +1,219 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! This module provides slicing of transfer frames across several consecutive LDPC codewords,
+//! as permitted for TM frames by CCSDS 131.0-B.
+//!
+//! On transmit, `encode_frame` splits a frame of any length into consecutive k-bit messages
+//! and encodes each into a codeword. If the frame length is not a multiple of k/8 bytes,
+//! the final message is padded with zeros. (The C2 code's 7136-bit chunks are not needed here
+//! as the C2 code is not implemented.)
+//!
+//! On receive, a [`FrameAssembler`](struct.FrameAssembler.html) collects the decoder output for
+//! each codeword in turn, and once a whole frame has arrived reports how many of the codewords
+//! it spans failed to decode, so frames with uncorrected errors can be discarded or flagged.
+//!
+//! ```
+//! # use labrador_ldpc::LDPCCode;
+//! use labrador_ldpc::framing::FrameAssembler;
+//!
+//! let code = LDPCCode::TC128;
+//! let frame: Vec<u8> = (0..20).collect();
+//! let mut codewords = vec![0u8; code.frame_codewords(frame.len()) * code.n()/8];
+//! code.encode_frame(&frame, &mut codewords);
+//!
+//! let mut rxframe = vec![0u8; 20];
+//! let mut assembler = FrameAssembler::new(code, &mut rxframe);
+//! let mut working = vec![0u8; code.decode_bf_working_len()];
+//! let mut output = vec![0u8; code.output_len()];
+//! for codeword in codewords.chunks(code.n()/8) {
+//!     let (success, _) = code.decode_bf(codeword, &mut output, &mut working, 20);
+//!     if let Some(status) = assembler.push(&output, success) {
+//!         assert_eq!(status.failed_codewords, 0);
+//!         assert_eq!(assembler.frame(), &frame[..]);
+//!     }
+//! }
+//! ```
+
+use ::codes::LDPCCode;
+
+/// Status of a completely reassembled frame.
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub struct FrameStatus {
+    /// Number of codewords the frame spanned.
+    pub codewords: usize,
+
+    /// Number of those codewords which failed to decode.
+    pub failed_codewords: usize,
+}
+
+impl FrameStatus {
+    /// Returns true if every codeword in the frame decoded successfully.
+    pub fn is_ok(&self) -> bool {
+        self.failed_codewords == 0
+    }
+}
+
+/// Reassembles frames from consecutive decoded codewords.
+///
+/// The caller provides storage for one frame, whose length sets the frame length.
+/// Decoder output is pushed in one codeword at a time; when the final codeword of a frame is
+/// pushed, the frame is complete and a `FrameStatus` is returned. The next push starts a
+/// new frame.
+pub struct FrameAssembler<'a> {
+    code: LDPCCode,
+    frame: &'a mut [u8],
+    frame_len: usize,
+    codewords: usize,
+    failed: usize,
+}
+
+impl<'a> FrameAssembler<'a> {
+    /// Create a new assembler for frames of `frame.len()` bytes encoded with `code`.
+    pub fn new(code: LDPCCode, frame: &'a mut [u8]) -> FrameAssembler<'a> {
+        assert!(!frame.is_empty(), "frame must not be empty");
+        FrameAssembler { code, frame, frame_len: 0, codewords: 0, failed: 0 }
+    }
+
+    /// Push the next decoded codeword.
+    ///
+    /// `decoded` is the output from a decoder (at least k/8 bytes, of which only the first k/8
+    /// are used) and `success` is the success flag it returned.
+    ///
+    /// Returns `Some(FrameStatus)` if this codeword completed a frame, which can then be read
+    /// with `frame()`, otherwise `None`.
+    pub fn push(&mut self, decoded: &[u8], success: bool) -> Option<FrameStatus> {
+        let k = self.code.k() / 8;
+        assert!(decoded.len() >= k, "decoded.len() < k/8");
+
+        // Start a new frame if the previous one was completed
+        if self.frame_len == self.frame.len() {
+            self.frame_len = 0;
+            self.codewords = 0;
+            self.failed = 0;
+        }
+
+        let len = if self.frame.len() - self.frame_len < k {
+            self.frame.len() - self.frame_len
+        } else {
+            k
+        };
+        self.frame[self.frame_len..self.frame_len + len].copy_from_slice(&decoded[..len]);
+        self.frame_len += len;
+        self.codewords += 1;
+        if !success {
+            self.failed += 1;
+        }
+
+        if self.frame_len == self.frame.len() {
+            Some(FrameStatus { codewords: self.codewords, failed_codewords: self.failed })
+        } else {
+            None
+        }
+    }
+
+    /// Get the most recently completed frame, or the partial frame so far.
+    pub fn frame(&self) -> &[u8] {
+        &self.frame[..self.frame_len]
+    }
+
+    /// Discard any partially assembled frame, for example after losing synchronisation.
+    pub fn reset(&mut self) {
+        self.frame_len = 0;
+        self.codewords = 0;
+        self.failed = 0;
+    }
+}
+
+impl LDPCCode {
+    /// Get the number of codewords required to carry a frame of `frame_len` bytes.
+    pub fn frame_codewords(&self, frame_len: usize) -> usize {
+        frame_len.div_ceil(self.k() / 8)
+    }
+
+    /// Encode `frame` into consecutive codewords.
+    ///
+    /// `codewords` must be exactly `frame_codewords(frame.len()) * n/8` bytes long, and is
+    /// written with each codeword in turn. The final message is padded with zeros if `frame`
+    /// is not a multiple of k/8 bytes long.
+    pub fn encode_frame(&self, frame: &[u8], codewords: &mut [u8]) {
+        let k = self.k() / 8;
+        let n = self.n() / 8;
+        assert_eq!(codewords.len(), self.frame_codewords(frame.len()) * n,
+                   "codewords.len() incorrect");
+
+        for (chunk, codeword) in frame.chunks(k).zip(codewords.chunks_mut(n)) {
+            codeword[..chunk.len()].copy_from_slice(chunk);
+            for x in &mut codeword[chunk.len()..k] { *x = 0 }
+            self.encode(codeword);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+
+    use ::codes::LDPCCode;
+    use super::FrameAssembler;
+
+    #[test]
+    fn test_encode_frame() {
+        let code = LDPCCode::TM1280;
+        let frame: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        assert_eq!(code.frame_codewords(frame.len()), 3);
+        let mut codewords = vec![0u8; 3 * code.n()/8];
+        code.encode_frame(&frame, &mut codewords);
+
+        for (idx, codeword) in codewords.chunks(code.n()/8).enumerate() {
+            let mut message = vec![0u8; code.k()/8];
+            let chunk = &frame[idx*128..];
+            let len = if chunk.len() < 128 { chunk.len() } else { 128 };
+            message[..len].copy_from_slice(&chunk[..len]);
+            let mut expected = vec![0u8; code.n()/8];
+            code.copy_encode(&message, &mut expected);
+            assert_eq!(codeword, &expected[..]);
+        }
+    }
+
+    #[test]
+    fn test_frame_assembler() {
+        let code = LDPCCode::TC256;
+        let frames: Vec<Vec<u8>> = (0..3).map(|f| (0..40).map(|i| (i + f) as u8).collect())
+                                         .collect();
+        let mut codewords = Vec::new();
+        for frame in &frames {
+            let mut cw = vec![0u8; code.frame_codewords(frame.len()) * code.n()/8];
+            code.encode_frame(frame, &mut cw);
+            codewords.extend_from_slice(&cw);
+        }
+
+        // Destroy the second codeword of the second frame
+        let n = code.n()/8;
+        for x in &mut codewords[4*n..4*n + n/2] { *x ^= 0x96 }
+
+        let mut rxframe = vec![0u8; 40];
+        let mut assembler = FrameAssembler::new(code, &mut rxframe);
+        let mut working = vec![0u8; code.decode_bf_working_len()];
+        let mut output = vec![0u8; code.output_len()];
+        let mut results = Vec::new();
+        for codeword in codewords.chunks(n) {
+            let (success, _) = code.decode_bf(codeword, &mut output, &mut working, 20);
+            if let Some(status) = assembler.push(&output, success) {
+                results.push((status, assembler.frame().to_vec()));
+            }
+        }
+
+        assert_eq!(results.len(), 3);
+        for (idx, &(status, ref frame)) in results.iter().enumerate() {
+            assert_eq!(status.codewords, 3);
+            if idx == 1 {
+                assert!(!status.is_ok());
+                assert_eq!(status.failed_codewords, 1);
+            } else {
+                assert!(status.is_ok());
+                assert_eq!(frame, &frames[idx]);
+            }
+        }
+    }
+}
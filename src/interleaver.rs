@@ -0,0 +1,225 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! This module provides a block bit interleaver, for use between the encoder and a
+//! higher-order modulation mapper.
+//!
+//! Bits are written into a matrix column by column and read out row by row, as in DVB-S2
+//! (EN 302 307) where the number of columns equals the bits per symbol. Each symbol then
+//! carries bits from widely separated parts of the codeword, so a symbol error or a
+//! weakly-protected bit position does not concentrate errors in one region of the code.
+//! If the length is not a multiple of the number of columns, the last column is shorter and
+//! its missing cells are skipped when reading out.
+//!
+//! On transmit, `interleave` permutes packed hard bits; on receive, `deinterleave_llrs`
+//! reverses the permutation on the demapped LLRs before `decode_ms`.
+//!
+//! ```
+//! # use labrador_ldpc::LDPCCode;
+//! use labrador_ldpc::interleaver::BlockInterleaver;
+//!
+//! let code = LDPCCode::TM2048;
+//! let il = BlockInterleaver::for_code(code, 4);
+//!
+//! let txdata: Vec<u8> = (0..code.k()/8).map(|i| i as u8).collect();
+//! let mut txcode = vec![0u8; code.n()/8];
+//! code.copy_encode(&txdata, &mut txcode);
+//! let mut txbits = vec![0u8; code.n()/8];
+//! il.interleave(&txcode, &mut txbits);
+//!
+//! // ... map, transmit, receive and demap txbits into LLRs ...
+//! let mut rxllrs = vec![0i8; code.n()];
+//! code.hard_to_llrs(&txbits, &mut rxllrs);
+//!
+//! let mut llrs = vec![0i8; code.n()];
+//! il.deinterleave_llrs(&rxllrs, &mut llrs);
+//!
+//! let mut working = vec![0i8; code.decode_ms_working_len()];
+//! let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+//! let mut rxdata = vec![0u8; code.output_len()];
+//! code.decode_ms(&llrs, &mut rxdata, &mut working, &mut working_u8, 20);
+//! assert_eq!(&rxdata[..code.k()/8], &txdata[..]);
+//! ```
+
+use ::codes::LDPCCode;
+
+/// Column-write, row-read block interleaver.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub struct BlockInterleaver {
+    n: usize,
+    cols: usize,
+    rows: usize,
+    reversed: bool,
+}
+
+impl BlockInterleaver {
+    /// Create a new interleaver for `n` bits using `cols` columns.
+    pub fn new(n: usize, cols: usize) -> BlockInterleaver {
+        assert!(n > 0, "n must be at least 1");
+        assert!(cols > 0 && cols <= n, "cols must be between 1 and n");
+        BlockInterleaver { n, cols, rows: n.div_ceil(cols), reversed: false }
+    }
+
+    /// Create a new interleaver for the transmitted codewords of `code`, which have n bits.
+    pub fn for_code(code: LDPCCode, cols: usize) -> BlockInterleaver {
+        BlockInterleaver::new(code.n(), cols)
+    }
+
+    /// Read each row from the last column to the first instead.
+    ///
+    /// DVB-S2 uses this for rate 3/5 8PSK.
+    pub fn reversed(mut self) -> BlockInterleaver {
+        self.reversed = !self.reversed;
+        self
+    }
+
+    /// Get the number of bits interleaved.
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    /// Get the number of columns.
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// Get the number of rows, including the partial last row if any.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Number of cells filled in column `c`.
+    fn col_len(&self, c: usize) -> usize {
+        let start = c * self.rows;
+        if start >= self.n {
+            0
+        } else if self.n - start < self.rows {
+            self.n - start
+        } else {
+            self.rows
+        }
+    }
+
+    /// Get the output position of input bit `i`.
+    pub fn position(&self, i: usize) -> usize {
+        assert!(i < self.n, "i out of range");
+        let r = i % self.rows;
+        let c = i / self.rows;
+
+        // Every filled cell in earlier rows, plus those earlier in this row
+        let mut pos = 0;
+        for cc in 0..self.cols {
+            let len = self.col_len(cc);
+            pos += if len < r { len } else { r };
+            let before = if self.reversed { cc > c } else { cc < c };
+            if before && len > r {
+                pos += 1;
+            }
+        }
+        pos
+    }
+
+    /// Interleave the first n bits of packed hard bits `input` into `output`.
+    ///
+    /// Both must be at least n/8 bytes (rounded up). Bits are MSB first; any trailing bits
+    /// of `output` after the first n are left unchanged.
+    pub fn interleave(&self, input: &[u8], output: &mut [u8]) {
+        self.check_bytes(input, output);
+        for i in 0..self.n {
+            let bit = input[i/8] >> (7 - (i%8)) & 1;
+            set_bit(output, self.position(i), bit);
+        }
+    }
+
+    /// Reverse `interleave`.
+    pub fn deinterleave(&self, input: &[u8], output: &mut [u8]) {
+        self.check_bytes(input, output);
+        for i in 0..self.n {
+            let j = self.position(i);
+            let bit = input[j/8] >> (7 - (j%8)) & 1;
+            set_bit(output, i, bit);
+        }
+    }
+
+    /// Interleave the n soft values in `input` into `output`, both exactly n long.
+    pub fn interleave_llrs<T: Copy>(&self, input: &[T], output: &mut [T]) {
+        assert_eq!(input.len(), self.n, "input.len() != n");
+        assert_eq!(output.len(), self.n, "output.len() != n");
+        for (i, x) in input.iter().enumerate() {
+            output[self.position(i)] = *x;
+        }
+    }
+
+    /// Reverse `interleave_llrs`, for example on demapped LLRs before decoding.
+    pub fn deinterleave_llrs<T: Copy>(&self, input: &[T], output: &mut [T]) {
+        assert_eq!(input.len(), self.n, "input.len() != n");
+        assert_eq!(output.len(), self.n, "output.len() != n");
+        for (i, x) in output.iter_mut().enumerate() {
+            *x = input[self.position(i)];
+        }
+    }
+
+    fn check_bytes(&self, input: &[u8], output: &[u8]) {
+        let bytes = self.n.div_ceil(8);
+        assert!(input.len() >= bytes, "input shorter than n bits");
+        assert!(output.len() >= bytes, "output shorter than n bits");
+    }
+}
+
+fn set_bit(data: &mut [u8], i: usize, bit: u8) {
+    let mask = 1 << (7 - (i%8));
+    if bit == 1 {
+        data[i/8] |= mask;
+    } else {
+        data[i/8] &= !mask;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+
+    use ::codes::LDPCCode;
+    use super::BlockInterleaver;
+
+    #[test]
+    fn test_positions() {
+        let il = BlockInterleaver::new(10, 3);
+        assert_eq!(il.rows(), 4);
+        let mut out = vec![0; 10];
+        let input: Vec<usize> = (0..10).collect();
+        il.interleave_llrs(&input, &mut out);
+        assert_eq!(out, vec![0, 4, 8, 1, 5, 9, 2, 6, 3, 7]);
+
+        let il = il.reversed();
+        il.interleave_llrs(&input, &mut out);
+        assert_eq!(out, vec![8, 4, 0, 9, 5, 1, 6, 2, 7, 3]);
+
+        let mut back = vec![0; 10];
+        il.deinterleave_llrs(&out, &mut back);
+        assert_eq!(back, input);
+    }
+
+    #[test]
+    fn test_bits() {
+        for cols in 1..6 {
+            let code = LDPCCode::TM1280;
+            let il = BlockInterleaver::for_code(code, cols);
+            let data: Vec<u8> = (0..code.n()/8).map(|i| (i * 73 + 5) as u8).collect();
+            let mut inter = vec![0u8; data.len()];
+            il.interleave(&data, &mut inter);
+            let mut back = vec![0u8; data.len()];
+            il.deinterleave(&inter, &mut back);
+            assert_eq!(back, data);
+
+            // The packed and soft versions agree
+            let llrs: Vec<i8> = (0..code.n())
+                .map(|i| if data[i/8] >> (7 - (i%8)) & 1 == 1 { -1 } else { 1 }).collect();
+            let mut inter_llrs = vec![0i8; code.n()];
+            il.interleave_llrs(&llrs, &mut inter_llrs);
+            for (i, llr) in inter_llrs.iter().enumerate() {
+                assert_eq!(*llr == -1, inter[i/8] >> (7 - (i%8)) & 1 == 1);
+            }
+        }
+    }
+}
@@ -143,6 +143,15 @@
 //! assert_eq!(codeword, [0x0706050403020100, 0x5662E19487989934]);
 //! ```
 //!
+//! If your codeword lives inside a larger frame and is not byte-aligned, `encode_bits` and
+//! `copy_encode_bits` take a [`BitSliceMut`](bits/struct.BitSliceMut.html) instead, which can
+//! start at any bit offset and number bits either MSB-first or LSB-first within each byte. The
+//! decoders have matching `decode_bf_bits`, `hard_bits_to_llrs` and `llrs_to_hard_bits`.
+//!
+//! If the message arrives in small chunks (for example from a DMA buffer), the
+//! [`StreamEncoder`](encoder/struct.StreamEncoder.html) accumulates the parity bits as each
+//! chunk is fed in, so the complete message never needs to be held in memory at once.
+//!
 //! The required memory (in bytes) to encode with each code is:
 //!
 //! Code   | Input (RAM) | Output (RAM)    | Generator const (text)
@@ -199,6 +208,29 @@
 //! runtime with methods on `LDPCCode` such as `decode_ms_working_len()`. You can therefore
 //! allocate the required memory either statically or dynamically at runtime.
 //!
+//! For hard real-time use, the run time of both decoders is dominated by visits to the edges
+//! of the parity check matrix, of which there are `paritycheck_sum`. The worst-case number of
+//! edge visits per iteration is:
+//!
+//! Code   | `paritycheck_sum` | `bf` visits per iteration | `mp` visits per iteration
+//! -------|-------------------|---------------------------|--------------------------
+//!        |                   | =2*ps (4*ps if punctured) | =2*ps
+//! TC128  |               512 |                      1024 |                      1024
+//! TC256  |              1024 |                      2048 |                      2048
+//! TC512  |              2048 |                      4096 |                      4096
+//! TM1280 |              4992 |                     19968 |                      9984
+//! TM1536 |              5888 |                     23552 |                     11776
+//! TM2048 |              7680 |                     30720 |                     15360
+//! TM5120 |             19968 |                     79872 |                     39936
+//! TM6144 |             23552 |                     94208 |                     47104
+//! TM8192 |             30720 |                    122880 |                     61440
+//!
+//! `decode_bf_edge_visits` and `decode_ms_edge_visits` compute the total for a given
+//! `maxiters`, where for punctured codes the `bf` figure includes the erasure decoding stage
+//! that runs first. To bound the wall-clock time as well, `decode_bf_deadline` and
+//! `decode_ms_deadline` poll a closure after every iteration and stop as soon as it reports
+//! that a deadline has passed.
+//!
 //! Please see the individual decoder methods for more details on their requirements.
 //!
 //! ### Bit Flipping Decoder
@@ -216,12 +248,106 @@
 //! so inherently covers the punctured codes as well. This implementation is based on one described
 //! by Savin, arXiv:0803.1090. It is both reasonably efficient (no `atahn` required), and
 //! performs very close to optimal sum-product decoding.
+//!
+//! For schedulers which give the decoder fixed time slices, the resumable
+//! [`MsDecoder`](decoder/struct.MsDecoder.html) runs a few iterations at a time and continues
+//! later from where it left off, giving the same result as a single `decode_ms` call.
+//!
+//! Failing frames normally run for the full `maxiters` iterations; `decode_ms_early` takes an
+//! `EarlyStopping` configuration to give up sooner when the hard decisions stop changing, the
+//! number of unsatisfied checks stops falling, or the marginals saturate.
+//! `decode_ms_confidence` additionally reports the smallest and mean posterior |LLR| of the
+//! information bits and an estimated bit error probability, to flag frames which satisfied
+//! every parity check but only with a low margin.
+//! For link telemetry, `decode_ms_link` in [`snr`](snr/index.html) also estimates the channel
+//! Es/N0 and bit error rate, from the decoded codeword or blind if decoding failed.
+//! To lower the error floor, `decode_ms_trapping` in [`trapping`](trapping/index.html) retries
+//! frames left with only a few unsatisfied checks, modifying the LLRs of the variables
+//! connected to them to escape trapping sets.
+//!
+//! For the short TC codes, [`osd`](osd/index.html) adds an ordered-statistics decoding
+//! fallback after `decode_ms` fails, approaching maximum likelihood performance at the cost of
+//! an extra working area of 1216, 4480 or 17152 bytes for TC128, TC256 or TC512 respectively.
+//!
+//! For verifying marginal frames on the ground, [`chase`](chase/index.html) provides Chase-II
+//! list decoding around `decode_bf`, reporting how strongly the best codeword is preferred.
+//!
+//! The [`quantise`](quantise/index.html) module converts floating point samples or LLRs into
+//! fixed-point decoder types with suitable headroom, optionally choosing the scale
+//! automatically from the estimated noise level.
+//! The [`demapper`](demapper/index.html) module produces LLRs directly from complex I/Q
+//! samples for QPSK, 8PSK and 16/32-APSK, using exact log-MAP or max-log demapping.
+//! The [`interleaver`](interleaver/index.html) module provides the block bit interleaving
+//! used between the encoder and such higher-order modulations, and [`bicm`](bicm/index.html)
+//! iterates between the demapper and `decode_ms_extrinsic` to improve on a single pass.
+//!
+//! ## Link Layer Helpers
+//!
+//! A few other pieces of the CCSDS coding sublayer are provided so the codes can be used on a
+//! real link without a separate library:
+//!
+//! * [`randomizer`](randomizer/index.html) applies and removes the TM and TC pseudo-randomizer
+//!   sequences, on either hard bits or LLRs.
+//! * [`asm`](asm/index.html) inserts attached sync markers on transmit and finds codeword
+//!   boundaries (and inverted polarity) in a received LLR stream.
+//! * [`cltu`](cltu/index.html) wraps telecommand frames into CLTUs using the TC codes, and
+//!   finds and decodes them again on receive.
+//! * [`framing`](framing/index.html) slices TM transfer frames across several codewords and
+//!   reassembles them on receive, reporting frames containing failed codewords.
+//! * [`crc`](crc/index.html) computes CRC-16-CCITT and configurable CRC-32 checksums, and
+//!   provides `decode_ms_crc` which keeps iterating until the decoded message's CRC matches.
+//!
+//! ## Simulation
+//!
+//! With the `std` feature enabled, the [`sim`](sim/index.html) module provides a seeded
+//! channel simulator with BPSK/QPSK modulation over AWGN, for evaluating code performance.
+//! The rest of the crate remains `no_std` either way.
+//!
+//! The `labrador-sim` binary (also requiring `std`) uses it to sweep Eb/N0 for chosen codes and
+//! decoders, writing bit and frame error rates and average iterations as CSV or JSON:
+//! `cargo run --release --features std --bin labrador-sim -- --help`.
+//!
+//! The [`analysis`](analysis/index.html) module, also requiring `std`, builds each code's
+//! Tanner graph and reports its degree distributions, girth, and counts of short cycles, and
+//! the [`matrix`](matrix/index.html) module exports and imports parity check and generator
+//! matrices in alist, Matrix Market, PBM and CSV formats for use with other LDPC tools. The
+//! [`distance`](distance/index.html) module searches the TC codes for low-weight codewords, to
+//! estimate their minimum distance and weight enumerators.
 
-#[cfg(test)]
+#[cfg(any(test, feature = "std"))]
 #[macro_use]
 extern crate std;
 
 pub mod codes;
 pub mod encoder;
 pub mod decoder;
+pub mod bits;
+pub mod randomizer;
+pub mod asm;
+pub mod cltu;
+pub mod framing;
+pub mod crc;
+pub mod quantise;
+pub mod demapper;
+pub mod interleaver;
+pub mod bicm;
+pub mod osd;
+pub mod chase;
+pub mod snr;
+pub mod trapping;
+
+mod math;
+
+#[cfg(any(test, feature = "std"))]
+pub mod sim;
+
+#[cfg(any(test, feature = "std"))]
+pub mod analysis;
+
+#[cfg(any(test, feature = "std"))]
+pub mod matrix;
+
+#[cfg(any(test, feature = "std"))]
+pub mod distance;
+
 pub use codes::{LDPCCode};
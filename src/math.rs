@@ -0,0 +1,138 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! Small floating point helpers which `core` does not provide, so the crate can stay `no_std`.
+//!
+//! These favour simplicity over the last bit of accuracy; they are used for estimates and
+//! scale factors, not in the decoders' inner loops.
+
+/// Square root of `x`, or 0 for non-positive `x`.
+pub(crate) fn sqrtf(x: f32) -> f32 {
+    if x <= 0.0 || x.is_nan() {
+        return 0.0;
+    }
+    if x.is_infinite() {
+        return x;
+    }
+
+    // Initial guess by halving the exponent, then refine with Newton's method
+    let mut y = f32::from_bits((x.to_bits() >> 1) + 0x1FC0_0000);
+    for _ in 0..4 {
+        y = 0.5 * (y + x / y);
+    }
+    y
+}
+
+/// Exponential of `x`.
+pub(crate) fn expf(x: f32) -> f32 {
+    if x.is_nan() {
+        return x;
+    }
+    if x < -87.0 {
+        return 0.0;
+    }
+    if x > 88.0 {
+        return f32::INFINITY;
+    }
+
+    // Reduce to x = k ln2 + r with |r| <= ln2/2, then use a Taylor series for exp(r)
+    let kf = x * ::core::f32::consts::LOG2_E;
+    let k = if kf >= 0.0 { (kf + 0.5) as i32 } else { (kf - 0.5) as i32 };
+    // ln2 is split into a short high part, so k*hi is exact, and a low correction part
+    let r = (x - k as f32 * 0.693_145_75) - k as f32 * 1.428_606_8e-6;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for i in 1..9 {
+        term *= r / i as f32;
+        sum += term;
+    }
+    sum * f32::from_bits(((k + 127) as u32) << 23)
+}
+
+/// Natural logarithm of `x`, or negative infinity for non-positive `x`.
+pub(crate) fn lnf(x: f32) -> f32 {
+    if x.is_nan() || x.is_infinite() {
+        return x;
+    }
+    if x <= 0.0 {
+        return f32::NEG_INFINITY;
+    }
+
+    // Normalise subnormals, then split into x = m * 2^e with m in [1/sqrt2, sqrt2)
+    let (x, mut e) = if x < f32::MIN_POSITIVE { (x * 8388608.0, -23) } else { (x, 0) };
+    let bits = x.to_bits();
+    e += ((bits >> 23) & 0xFF) as i32 - 127;
+    let mut m = f32::from_bits((bits & 0x007F_FFFF) | 0x3F80_0000);
+    if m > ::core::f32::consts::SQRT_2 {
+        m *= 0.5;
+        e += 1;
+    }
+
+    // ln(m) = 2 atanh((m-1)/(m+1)), as a series in t = (m-1)/(m+1), |t| < 0.172
+    let t = (m - 1.0) / (m + 1.0);
+    let t2 = t * t;
+    let series = t * (2.0 + t2 * (2.0/3.0 + t2 * (2.0/5.0 + t2 * (2.0/7.0 + t2 * (2.0/9.0)))));
+    series + e as f32 * ::core::f32::consts::LN_2
+}
+
+/// Jacobian logarithm, ln(exp(a) + exp(b)).
+pub(crate) fn maxstar(a: f32, b: f32) -> f32 {
+    let (max, diff) = if a > b { (a, a - b) } else { (b, b - a) };
+    if max == f32::NEG_INFINITY {
+        return max;
+    }
+    max + lnf(1.0 + expf(-diff))
+}
+
+/// Complementary error function of `x`, with fractional error below about 1e-6.
+pub(crate) fn erfcf(x: f32) -> f32 {
+    // Chebyshev fit from Numerical Recipes
+    let z = if x < 0.0 { -x } else { x };
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let p = -1.265_512_2 + t * (1.000_023_7 + t * (0.374_091_96 + t * (0.096_784_18
+            + t * (-0.186_288_06 + t * (0.278_868_07 + t * (-1.135_204
+            + t * (1.488_515_9 + t * (-0.822_152_23 + t * 0.170_872_77))))))));
+    let r = t * expf(-z * z + p);
+    if x >= 0.0 { r } else { 2.0 - r }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sqrtf, expf, lnf, maxstar, erfcf};
+
+    #[test]
+    fn test_sqrtf() {
+        for &x in &[1e-20f32, 1e-6, 0.25, 1.0, 2.0, 10.0, 12345.0, 1e20] {
+            let rel = (sqrtf(x) - x.sqrt()).abs() / x.sqrt();
+            assert!(rel < 1e-6, "sqrtf({}) = {}", x, sqrtf(x));
+        }
+        assert_eq!(sqrtf(0.0), 0.0);
+        assert_eq!(sqrtf(-1.0), 0.0);
+    }
+
+    #[test]
+    fn test_expf_lnf() {
+        for i in -80..80 {
+            let x = i as f32 * 0.97;
+            let rel = (expf(x) - x.exp()).abs() / x.exp();
+            assert!(rel < 1e-6, "expf({}) = {}", x, expf(x));
+        }
+        for &x in &[1e-40f32, 1e-10, 0.01, 0.5, 0.99, 1.0, 1.5, 2.0, 100.0, 3e30] {
+            assert!((lnf(x) - x.ln()).abs() < 1e-5, "lnf({}) = {}", x, lnf(x));
+        }
+        assert_eq!(expf(-100.0), 0.0);
+        assert_eq!(lnf(0.0), f32::NEG_INFINITY);
+        assert!((maxstar(1.0, 2.0) - (1.0f32.exp() + 2.0f32.exp()).ln()).abs() < 1e-6);
+        assert_eq!(maxstar(f32::NEG_INFINITY, 3.0), 3.0);
+    }
+
+    #[test]
+    fn test_erfcf() {
+        // Reference values of erfc
+        let cases = [(0.0f32, 1.0f32), (0.5, 0.479_500_1), (1.0, 0.157_299_2),
+                     (2.0, 4.677_735e-3), (3.0, 2.209_05e-5), (-1.0, 1.842_700_8)];
+        for &(x, y) in &cases {
+            assert!((erfcf(x) - y).abs() / y < 1e-5);
+        }
+    }
+}
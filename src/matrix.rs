@@ -0,0 +1,538 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! This module exports and imports binary matrices in standard file formats, for
+//! cross-validating against other LDPC tools such as MATLAB, AFF3CT and GNU Radio.
+//!
+//! `SparseMatrix` holds the positions of the ones in a binary matrix. It can be built from a
+//! code's parity check matrix (expanded by `iter_paritychecks`) or generator matrix (expanded
+//! from `compact_generator`), or read from a file, and written out as:
+//!
+//! * MacKay's alist format, with `write_alist` and `read_alist`
+//! * Matrix Market coordinate format (`.mtx`), with `write_mtx` and `read_mtx`
+//! * Dense binary PBM images (`.pbm`), with `write_pbm` and `read_pbm`, where ones are black
+//! * Dense CSV of zeros and ones, with `write_csv` and `read_csv`
+//!
+//! Row and column indices follow the rest of the crate: parity check matrix rows are checks
+//! and columns are variables, including the punctured variables after the first n, while
+//! generator matrix rows are data bits and columns are codeword bits. All formats except CSV
+//! write 1-indexed positions where they store positions at all.
+//!
+//! Reading a matrix for a code not included in this crate gives its ones through `entries`,
+//! the same (row, column) pairs that `iter_paritychecks` gives for included codes.
+//!
+//! This module requires the `std` feature.
+//!
+//! ```
+//! # use labrador_ldpc::LDPCCode;
+//! use labrador_ldpc::matrix::SparseMatrix;
+//!
+//! let h = SparseMatrix::parity_check(LDPCCode::TC128);
+//! let mut alist = Vec::new();
+//! h.write_alist(&mut alist).unwrap();
+//! assert!(alist.starts_with(b"128 64\n5 8\n"));
+//!
+//! let h2 = SparseMatrix::read_alist(&mut &alist[..]).unwrap();
+//! assert_eq!(h, h2);
+//! ```
+
+use std::io::{self, Read, Write};
+use std::string::String;
+use std::vec::Vec;
+
+use ::codes::LDPCCode;
+
+/// A binary matrix, stored as the positions of its ones.
+#[derive(Clone,Debug,PartialEq,Eq,Hash)]
+pub struct SparseMatrix {
+    rows: usize,
+    cols: usize,
+    entries: Vec<(usize, usize)>,
+}
+
+impl SparseMatrix {
+    /// Create a matrix with `rows` rows and `cols` columns, with ones at each (row, column)
+    /// in `entries`.
+    ///
+    /// Entries may be in any order. Entries repeated an even number of times cancel out, as
+    /// for a sum over GF(2).
+    pub fn new(rows: usize, cols: usize, mut entries: Vec<(usize, usize)>) -> SparseMatrix {
+        assert!(entries.iter().all(|&(r, c)| r < rows && c < cols), "entry out of range");
+        entries.sort_unstable();
+        let mut kept: Vec<(usize, usize)> = Vec::with_capacity(entries.len());
+        for entry in entries {
+            if kept.last() == Some(&entry) {
+                kept.pop();
+            } else {
+                kept.push(entry);
+            }
+        }
+        SparseMatrix { rows, cols, entries: kept }
+    }
+
+    /// Create the parity check matrix of `code`, which has n + punctured_bits - k rows and
+    /// n + punctured_bits columns.
+    pub fn parity_check(code: LDPCCode) -> SparseMatrix {
+        let cols = code.n() + code.punctured_bits();
+        SparseMatrix::new(cols - code.k(), cols, code.iter_paritychecks().collect())
+    }
+
+    /// Create the systematic generator matrix of `code`, which has k rows and n columns,
+    /// so that multiplying a row vector of data by it gives the codeword `encode` produces.
+    pub fn generator(code: LDPCCode) -> SparseMatrix {
+        let k = code.k();
+        let r = code.n() - k;
+        let b = code.circulant_size();
+        let gc = code.compact_generator();
+        let row_len = r / 64;
+        let mut entries = Vec::new();
+
+        // Each row of circulants is given by its first row, packed MSB first into u64s, and
+        // each later row in the circulant is rotated right by one more bit within each block.
+        for row in 0..k {
+            entries.push((row, row));
+            let (crow, offset) = (row / b, row % b);
+            let first = &gc[crow*row_len..(crow+1)*row_len];
+            for col in 0..r {
+                let (block, j) = (col / b, col % b);
+                let i = block*b + (j + b - offset) % b;
+                if first[i/64] >> (63 - (i%64)) & 1 == 1 {
+                    entries.push((row, k + col));
+                }
+            }
+        }
+
+        SparseMatrix { rows: k, cols: code.n(), entries }
+    }
+
+    /// Get the number of rows.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Get the number of columns.
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// Get the (row, column) position of every one, sorted by row then column.
+    pub fn entries(&self) -> &[(usize, usize)] {
+        &self.entries
+    }
+
+    /// Check whether the matrix has a one at `row`, `col`.
+    pub fn get(&self, row: usize, col: usize) -> bool {
+        self.entries.binary_search(&(row, col)).is_ok()
+    }
+
+    /// Write in MacKay's alist format, with lists padded with zeros to the maximum weight.
+    pub fn write_alist<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let row_lists = self.row_lists();
+        let col_lists = self.col_lists();
+        let max_row = row_lists.iter().map(|l| l.len()).max().unwrap_or(0);
+        let max_col = col_lists.iter().map(|l| l.len()).max().unwrap_or(0);
+
+        writeln!(w, "{} {}", self.cols, self.rows)?;
+        writeln!(w, "{} {}", max_col, max_row)?;
+        write_line(w, col_lists.iter().map(|l| l.len()))?;
+        write_line(w, row_lists.iter().map(|l| l.len()))?;
+        for list in &col_lists {
+            write_line(w, list.iter().map(|x| x + 1).chain((list.len()..max_col).map(|_| 0)))?;
+        }
+        for list in &row_lists {
+            write_line(w, list.iter().map(|x| x + 1).chain((list.len()..max_row).map(|_| 0)))?;
+        }
+        Ok(())
+    }
+
+    /// Read MacKay's alist format, with or without zero padding.
+    ///
+    /// The row lists must agree with the column lists.
+    pub fn read_alist<R: Read>(r: &mut R) -> io::Result<SparseMatrix> {
+        let text = read_string(r)?;
+        let mut tokens = text.split_whitespace().map(|t| {
+            t.parse::<usize>().map_err(|_| invalid("alist: expected a non-negative integer"))
+        });
+        let mut next = || tokens.next().unwrap_or_else(|| Err(invalid("alist: unexpected end")));
+
+        let cols = next()?;
+        let rows = next()?;
+        next()?;
+        next()?;
+        let col_weights = (0..cols).map(|_| next()).collect::<io::Result<Vec<usize>>>()?;
+        let row_weights = (0..rows).map(|_| next()).collect::<io::Result<Vec<usize>>>()?;
+
+        // Padding zeros are skipped wherever they appear
+        let mut next_index = |len: usize| -> io::Result<usize> {
+            loop {
+                match next()? {
+                    0 => continue,
+                    x if x <= len => return Ok(x - 1),
+                    _ => return Err(invalid("alist: index out of range")),
+                }
+            }
+        };
+        let mut entries = Vec::new();
+        for (col, &weight) in col_weights.iter().enumerate() {
+            for _ in 0..weight {
+                entries.push((next_index(rows)?, col));
+            }
+        }
+        let mut from_rows = Vec::new();
+        for (row, &weight) in row_weights.iter().enumerate() {
+            for _ in 0..weight {
+                from_rows.push((row, next_index(cols)?));
+            }
+        }
+
+        let matrix = SparseMatrix::new(rows, cols, entries);
+        if matrix.entries.len() != from_rows.len()
+            || matrix != SparseMatrix::new(rows, cols, from_rows)
+        {
+            return Err(invalid("alist: row lists do not match column lists"));
+        }
+        Ok(matrix)
+    }
+
+    /// Write in Matrix Market coordinate pattern format.
+    pub fn write_mtx<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "%%MatrixMarket matrix coordinate pattern general")?;
+        writeln!(w, "{} {} {}", self.rows, self.cols, self.entries.len())?;
+        for &(row, col) in &self.entries {
+            writeln!(w, "{} {}", row + 1, col + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Read Matrix Market coordinate format.
+    ///
+    /// The field must be `pattern` or `integer`, where entries with odd values are ones, and
+    /// the symmetry must be `general`.
+    pub fn read_mtx<R: Read>(r: &mut R) -> io::Result<SparseMatrix> {
+        let text = read_string(r)?;
+        let mut lines = text.lines();
+        let header: Vec<String> = lines.next().unwrap_or("").split_whitespace()
+                                       .map(|t| t.to_lowercase()).collect();
+        if header.len() != 5 || header[0] != "%%matrixmarket" || header[1] != "matrix"
+            || header[2] != "coordinate" || header[4] != "general"
+        {
+            return Err(invalid("mtx: expected a general coordinate matrix"));
+        }
+        let values = match &header[3][..] {
+            "pattern" => false,
+            "integer" => true,
+            _ => return Err(invalid("mtx: field must be pattern or integer")),
+        };
+
+        let mut lines = lines.filter(|l| !l.starts_with('%') && !l.trim().is_empty());
+        let size = parse_line(lines.next().unwrap_or(""), 3, "mtx: bad size line")?;
+        if size[2] < 0 {
+            return Err(invalid("mtx: bad size line"));
+        }
+        let (rows, cols, nnz) = (size[0] as usize, size[1] as usize, size[2] as usize);
+
+        // Entries are pushed as they are read, since nnz comes from the file and may be bogus
+        let mut entries = Vec::new();
+        for _ in 0..nnz {
+            let fields = if values { 3 } else { 2 };
+            let entry = parse_line(lines.next().unwrap_or(""), fields, "mtx: bad entry line")?;
+            let (row, col) = (entry[0] as usize, entry[1] as usize);
+            if row == 0 || row > rows || col == 0 || col > cols {
+                return Err(invalid("mtx: index out of range"));
+            }
+            if !values || entry[2] % 2 != 0 {
+                entries.push((row - 1, col - 1));
+            }
+        }
+        Ok(SparseMatrix::new(rows, cols, entries))
+    }
+
+    /// Write as a binary (P4) PBM image, one pixel per element, with ones black.
+    pub fn write_pbm<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "P4\n{} {}", self.cols, self.rows)?;
+        let mut line = vec![0u8; self.cols.div_ceil(8)];
+        for row in self.row_lists() {
+            for x in line.iter_mut() { *x = 0 }
+            for col in row {
+                line[col/8] |= 1 << (7 - (col%8));
+            }
+            w.write_all(&line)?;
+        }
+        Ok(())
+    }
+
+    /// Read a binary (P4) or plain (P1) PBM image, with black pixels as ones.
+    ///
+    /// Images with zero width or height are rejected as invalid.
+    pub fn read_pbm<R: Read>(r: &mut R) -> io::Result<SparseMatrix> {
+        let mut data = Vec::new();
+        r.read_to_end(&mut data)?;
+        let mut pos = 0;
+        let magic = pbm_token(&data, &mut pos);
+        let cols = pbm_number(&data, &mut pos)?;
+        let rows = pbm_number(&data, &mut pos)?;
+        if rows == 0 || cols == 0 {
+            return Err(invalid("pbm: empty image"));
+        }
+        let pixels = rows.checked_mul(cols).ok_or_else(|| invalid("pbm: image too large"))?;
+        let mut entries = Vec::new();
+
+        if magic == b"P4" {
+            // Exactly one whitespace character separates the header from the raster
+            let stride = cols.div_ceil(8);
+            let end = rows.checked_mul(stride).and_then(|len| len.checked_add(pos + 1))
+                          .ok_or_else(|| invalid("pbm: image too large"))?;
+            let raster = data.get(pos + 1..end).ok_or_else(|| invalid("pbm: raster too short"))?;
+            for (row, line) in raster.chunks(stride).enumerate() {
+                for col in 0..cols {
+                    if line[col/8] >> (7 - (col%8)) & 1 == 1 {
+                        entries.push((row, col));
+                    }
+                }
+            }
+        } else if magic == b"P1" {
+            // Plain PBM pixels need no whitespace between them, but may have comments
+            let mut values = Vec::new();
+            let mut comment = false;
+            for &c in &data[pos..] {
+                match c {
+                    b'#' => comment = true,
+                    b'\n' | b'\r' => comment = false,
+                    _ if comment || c.is_ascii_whitespace() => (),
+                    b'0' | b'1' => values.push(c == b'1'),
+                    _ => return Err(invalid("pbm: unexpected character in raster")),
+                }
+            }
+            if values.len() < pixels {
+                return Err(invalid("pbm: raster too short"));
+            }
+            for (i, &pixel) in values[..pixels].iter().enumerate() {
+                if pixel {
+                    entries.push((i / cols, i % cols));
+                }
+            }
+        } else {
+            return Err(invalid("pbm: expected P1 or P4 format"));
+        }
+
+        Ok(SparseMatrix::new(rows, cols, entries))
+    }
+
+    /// Write as dense CSV, one line of comma separated zeros and ones per row.
+    pub fn write_csv<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut line = vec![b'0'; 2 * self.cols];
+        for row in self.row_lists() {
+            for (i, x) in line.iter_mut().enumerate() {
+                *x = if i % 2 == 0 { b'0' } else { b',' };
+            }
+            for col in row {
+                line[2*col] = b'1';
+            }
+            if let Some(last) = line.last_mut() {
+                *last = b'\n';
+            }
+            w.write_all(&line)?;
+        }
+        Ok(())
+    }
+
+    /// Read dense CSV of zeros and ones, where every non-empty line is a row.
+    pub fn read_csv<R: Read>(r: &mut R) -> io::Result<SparseMatrix> {
+        let text = read_string(r)?;
+        let mut rows = 0;
+        let mut cols = None;
+        let mut entries = Vec::new();
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            let mut count = 0;
+            for (col, field) in line.split(',').enumerate() {
+                match field.trim() {
+                    "0" => (),
+                    "1" => entries.push((rows, col)),
+                    _ => return Err(invalid("csv: fields must be 0 or 1")),
+                }
+                count += 1;
+            }
+            if *cols.get_or_insert(count) != count {
+                return Err(invalid("csv: rows have different lengths"));
+            }
+            rows += 1;
+        }
+        Ok(SparseMatrix::new(rows, cols.unwrap_or(0), entries))
+    }
+
+    /// Get the sorted column indices of the ones in each row.
+    fn row_lists(&self) -> Vec<Vec<usize>> {
+        let mut lists = vec![Vec::new(); self.rows];
+        for &(row, col) in &self.entries {
+            lists[row].push(col);
+        }
+        lists
+    }
+
+    /// Get the sorted row indices of the ones in each column.
+    fn col_lists(&self) -> Vec<Vec<usize>> {
+        let mut lists = vec![Vec::new(); self.cols];
+        for &(row, col) in &self.entries {
+            lists[col].push(row);
+        }
+        lists
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_string<R: Read>(r: &mut R) -> io::Result<String> {
+    let mut text = String::new();
+    r.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+fn write_line<W: Write, I: Iterator<Item=usize>>(w: &mut W, items: I) -> io::Result<()> {
+    let mut first = true;
+    for item in items {
+        if !first {
+            write!(w, " ")?;
+        }
+        write!(w, "{}", item)?;
+        first = false;
+    }
+    writeln!(w)
+}
+
+/// Parse a line of exactly `fields` whitespace separated integers.
+fn parse_line(line: &str, fields: usize, msg: &str) -> io::Result<Vec<i64>> {
+    let values = line.split_whitespace().map(|t| t.parse::<i64>())
+                     .collect::<Result<Vec<i64>, _>>().map_err(|_| invalid(msg))?;
+    if values.len() != fields || values.iter().take(2).any(|&x| x < 0) {
+        return Err(invalid(msg));
+    }
+    Ok(values)
+}
+
+/// Get the next PBM header token, skipping whitespace and comments.
+fn pbm_token<'a>(data: &'a [u8], pos: &mut usize) -> &'a [u8] {
+    loop {
+        while *pos < data.len() && data[*pos].is_ascii_whitespace() {
+            *pos += 1;
+        }
+        if *pos < data.len() && data[*pos] == b'#' {
+            while *pos < data.len() && data[*pos] != b'\n' {
+                *pos += 1;
+            }
+        } else {
+            break;
+        }
+    }
+    let start = *pos;
+    while *pos < data.len() && !data[*pos].is_ascii_whitespace() && data[*pos] != b'#' {
+        *pos += 1;
+    }
+    &data[start..*pos]
+}
+
+fn pbm_number(data: &[u8], pos: &mut usize) -> io::Result<usize> {
+    let token = pbm_token(data, pos);
+    let text = ::core::str::from_utf8(token).map_err(|_| invalid("pbm: bad header"))?;
+    text.parse().map_err(|_| invalid("pbm: bad header"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+
+    use ::codes::LDPCCode;
+    use super::SparseMatrix;
+
+    const CODES: [LDPCCode; 9] = [LDPCCode::TC128, LDPCCode::TC256, LDPCCode::TC512,
+                                  LDPCCode::TM1280, LDPCCode::TM1536, LDPCCode::TM2048,
+                                  LDPCCode::TM5120, LDPCCode::TM6144, LDPCCode::TM8192];
+
+    #[test]
+    fn test_generator() {
+        // Every row of the generator matrix is the encoding of a single set data bit
+        for &code in &CODES {
+            let g = SparseMatrix::generator(code);
+            assert_eq!((g.rows(), g.cols()), (code.k(), code.n()));
+            let mut data = vec![0u8; code.k()/8];
+            let mut codeword = vec![0u8; code.n()/8];
+            for row in (0..code.k()).step_by(code.circulant_size() / 4 + 1) {
+                data[row/8] = 1 << (7 - (row%8));
+                code.copy_encode(&data, &mut codeword);
+                data[row/8] = 0;
+                for col in 0..code.n() {
+                    assert_eq!(g.get(row, col), codeword[col/8] >> (7 - (col%8)) & 1 == 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for &code in &CODES[..4] {
+            for m in &[SparseMatrix::parity_check(code), SparseMatrix::generator(code)] {
+                let mut buf = Vec::new();
+                m.write_alist(&mut buf).unwrap();
+                assert_eq!(&SparseMatrix::read_alist(&mut &buf[..]).unwrap(), m);
+                buf.clear();
+                m.write_mtx(&mut buf).unwrap();
+                assert_eq!(&SparseMatrix::read_mtx(&mut &buf[..]).unwrap(), m);
+                buf.clear();
+                m.write_pbm(&mut buf).unwrap();
+                assert_eq!(&SparseMatrix::read_pbm(&mut &buf[..]).unwrap(), m);
+                buf.clear();
+                m.write_csv(&mut buf).unwrap();
+                assert_eq!(&SparseMatrix::read_csv(&mut &buf[..]).unwrap(), m);
+            }
+        }
+    }
+
+    #[test]
+    fn test_formats() {
+        let m = SparseMatrix::new(2, 3, vec![(1, 2), (0, 0), (1, 0), (0, 1), (0, 1), (0, 1)]);
+        assert_eq!(m.entries(), &[(0, 0), (0, 1), (1, 0), (1, 2)]);
+
+        let mut buf = Vec::new();
+        m.write_alist(&mut buf).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(),
+                   "3 2\n2 2\n2 1 1\n2 2\n1 2\n1 0\n2 0\n1 2\n1 3\n");
+        let mut buf = Vec::new();
+        m.write_mtx(&mut buf).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(),
+                   "%%MatrixMarket matrix coordinate pattern general\n\
+                    2 3 4\n1 1\n1 2\n2 1\n2 3\n");
+        let mut buf = Vec::new();
+        m.write_pbm(&mut buf).unwrap();
+        assert_eq!(buf, b"P4\n3 2\n\xc0\xa0");
+        let mut buf = Vec::new();
+        m.write_csv(&mut buf).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "1,1,0\n1,0,1\n");
+
+        // Variants written by other tools
+        let alist = b"3 2\n2 2\n2 1 1\n2 2\n1 2\n1\n2\n1 2\n1 3\n";
+        assert_eq!(SparseMatrix::read_alist(&mut &alist[..]).unwrap(), m);
+        let mtx = b"%%MatrixMarket matrix coordinate integer general\n% H\n2 3 5\n1 1 1\n\
+                    1 2 -1\n2 1 1\n2 2 2\n2 3 1\n";
+        assert_eq!(SparseMatrix::read_mtx(&mut &mtx[..]).unwrap(), m);
+        let pbm = b"P1\n# H\n3 2\n110\n1 0 1\n";
+        assert_eq!(SparseMatrix::read_pbm(&mut &pbm[..]).unwrap(), m);
+
+        // Malformed input
+        let bad_alist = b"3 2\n2 2\n2 1 1\n2 2\n1 2\n1\n2\n1 2\n2 3\n";
+        assert!(SparseMatrix::read_alist(&mut &bad_alist[..]).is_err());
+        assert!(SparseMatrix::read_mtx(&mut &b"2 3 0\n"[..]).is_err());
+        let mtx_header = "%%MatrixMarket matrix coordinate pattern general\n";
+        for size in &["2 3 -1\n", "2 3 18446744073709551615\n", "2 3 4000000000000000000\n"] {
+            let mtx = String::from(mtx_header) + size;
+            assert!(SparseMatrix::read_mtx(&mut mtx.as_bytes()).is_err());
+        }
+        assert!(SparseMatrix::read_pbm(&mut &b"P4\n3 2\n\xc0"[..]).is_err());
+        assert!(SparseMatrix::read_pbm(&mut &b"P4\n18446744073709551615 9\n\xc0"[..]).is_err());
+        assert!(SparseMatrix::read_pbm(&mut &b"P1\n4294967296 4294967296\n1"[..]).is_err());
+        assert!(SparseMatrix::read_pbm(&mut &b"P4\n0 0\n\n"[..]).is_err());
+        assert!(SparseMatrix::read_pbm(&mut &b"P4\n0 3\n\n"[..]).is_err());
+        assert!(SparseMatrix::read_pbm(&mut &b"P1\n3 0\n"[..]).is_err());
+        assert!(SparseMatrix::read_csv(&mut &b"1,1\n1,0,1\n"[..]).is_err());
+    }
+}
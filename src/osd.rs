@@ -0,0 +1,329 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! This module provides ordered-statistics decoding (OSD), as a fallback for when `decode_ms`
+//! fails on the short TC codes.
+//!
+//! OSD sorts the codeword bits by reliability, and uses Gaussian elimination on the generator
+//! matrix to find the k most reliable bits which are linearly independent. The hard decisions
+//! on those bits determine a single codeword (order 0). Order-1 OSD also tries every codeword
+//! obtained by flipping one of those k bits, and order-2 every pair, keeping the codeword which
+//! disagrees least with the received LLRs. For the TC codes this comes close to maximum
+//! likelihood decoding, at the cost of a working area holding the full k by n generator matrix
+//! and roughly k²/2 candidate codewords at order 2.
+//!
+//! `decode_ms_osd` runs `decode_ms` first and only falls back to OSD, using the decoder's final
+//! marginal LLRs as reliabilities, if it fails. OSD always outputs a valid codeword, but it
+//! may not be the transmitted one, so check it with a CRC or similar if that matters.
+//!
+//! OSD is only available for codes without punctured bits, that is the TC codes. The required
+//! memory (in bytes) for `osd_working` is:
+//!
+//! Code   | `osd` overhead (RAM)
+//! -------|---------------------
+//!        | =k*n/8 + 2k + n/2
+//! TC128  |                1216
+//! TC256  |                4480
+//! TC512  |               17152
+//!
+//! ```
+//! # use labrador_ldpc::LDPCCode;
+//! use labrador_ldpc::osd::OsdOutcome;
+//!
+//! let code = LDPCCode::TC128;
+//! let txdata: Vec<u8> = (0..8).collect();
+//! let mut txcode = vec![0u8; code.n()/8];
+//! code.copy_encode(&txdata, &mut txcode);
+//!
+//! // Received LLRs with a burst of less confident errors
+//! let mut llrs = vec![0i16; code.n()];
+//! code.hard_to_llrs(&txcode, &mut llrs);
+//! for (i, llr) in llrs.iter_mut().enumerate() {
+//!     *llr *= if i >= 20 && i < 40 { -32 } else { 64 };
+//! }
+//!
+//! let mut working = vec![0i16; code.decode_ms_working_len()];
+//! let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+//! let mut osd_working = vec![0u8; code.decode_osd_working_len()];
+//! let mut rxdata = vec![0u8; code.output_len()];
+//! let (outcome, _) = code.decode_ms_osd(&llrs, &mut rxdata, &mut working, &mut working_u8,
+//!                                       &mut osd_working, 20, 2);
+//! assert_eq!(outcome, OsdOutcome::Osd);
+//! assert_eq!(&rxdata[..8], &txdata[..]);
+//! ```
+
+use ::codes::LDPCCode;
+use ::decoder::FloatConvert;
+
+/// How `decode_ms_osd` found its output codeword.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub enum OsdOutcome {
+    /// `decode_ms` found a codeword, so OSD was not needed.
+    MinSum,
+
+    /// `decode_ms` failed, and the codeword was found by OSD.
+    Osd,
+}
+
+impl LDPCCode {
+    /// Get the length of [u8] required for the working area of `decode_osd`.
+    ///
+    /// Equal to k*n/8 + 2*k + n/2.
+    pub fn decode_osd_working_len(&self) -> usize {
+        self.k() * self.n() / 8 + 2 * self.k() + self.n() / 2
+    }
+
+    /// Decode using ordered-statistics decoding of the given `order` (0, 1 or 2).
+    ///
+    /// * `llrs` must be n long, and gives both the hard decisions and their reliabilities.
+    ///   These may be the channel LLRs, or the marginals from a failed `decode_ms` call.
+    /// * `output` must be allocated to (n+punctured_bits)/8 bytes, aka `output_len()`.
+    /// * `osd_working` must be allocated to `decode_osd_working_len()` bytes.
+    ///
+    /// Only codes without punctured bits (the TC codes) are supported.
+    ///
+    /// The chosen codeword is written to `output`. Returns its correlation discrepancy, the
+    /// sum of |llr| over bits where it disagrees with the hard decisions, which is zero when
+    /// the hard decisions were already a codeword. A large discrepancy relative to the typical
+    /// |llr| suggests the codeword is wrong.
+    pub fn decode_osd<T: FloatConvert>(&self, llrs: &[T], output: &mut [u8],
+                                     osd_working: &mut [u8], order: usize) -> f32
+    {
+        let n = self.n();
+        let k = self.k();
+        let row_len = n / 8;
+        assert_eq!(self.punctured_bits(), 0, "OSD requires a code without punctured bits");
+        assert_eq!(llrs.len(), n, "llrs.len() != n");
+        assert_eq!(output.len(), self.output_len(), "output.len() != output_len()");
+        assert_eq!(osd_working.len(), self.decode_osd_working_len(),
+                   "osd_working.len() != decode_osd_working_len()");
+        assert!(order <= 2, "order must be 0, 1 or 2");
+
+        let (matrix, osd_working) = osd_working.split_at_mut(k * row_len);
+        let (pivots, osd_working) = osd_working.split_at_mut(2 * k);
+        let (done, osd_working) = osd_working.split_at_mut(row_len);
+        let (hard, osd_working) = osd_working.split_at_mut(row_len);
+        let (diff0, diff) = osd_working.split_at_mut(row_len);
+
+        // Expand the generator matrix by encoding each unit vector
+        for (i, row) in matrix.chunks_mut(row_len).enumerate() {
+            for x in row.iter_mut() { *x = 0 }
+            row[i/8] = 1 << (7 - (i%8));
+            self.encode(row);
+        }
+
+        for x in hard.iter_mut() { *x = 0 }
+        for (i, llr) in llrs.iter().enumerate() {
+            if *llr <= T::zero() {
+                hard[i/8] |= 1 << (7 - (i%8));
+            }
+        }
+
+        // Reduce the matrix to systematic form on the most reliable independent columns,
+        // considering columns in decreasing order of reliability.
+        for x in done.iter_mut() { *x = 0 }
+        let mut rank = 0;
+        while rank < k {
+            let mut col = n;
+            let mut best = T::zero();
+            for (i, llr) in llrs.iter().enumerate() {
+                if done[i/8] >> (7 - (i%8)) & 1 == 0 && (col == n || llr.abs() > best) {
+                    col = i;
+                    best = llr.abs();
+                }
+            }
+            // The generator has full rank, so k independent columns always exist
+            assert!(col < n);
+            done[col/8] |= 1 << (7 - (col%8));
+
+            let pivot_row = (rank..k).find(|&r| get_bit(&matrix[r*row_len..], col));
+            if let Some(r) = pivot_row {
+                if r != rank {
+                    for j in 0..row_len {
+                        matrix.swap(r*row_len + j, rank*row_len + j);
+                    }
+                }
+                let (before, rest) = matrix.split_at_mut(rank * row_len);
+                let (pivot, after) = rest.split_at_mut(row_len);
+                for row in before.chunks_mut(row_len).chain(after.chunks_mut(row_len)) {
+                    if get_bit(row, col) {
+                        for (x, p) in row.iter_mut().zip(pivot.iter()) { *x ^= *p }
+                    }
+                }
+                pivots[2*rank] = (col >> 8) as u8;
+                pivots[2*rank + 1] = col as u8;
+                rank += 1;
+            }
+        }
+
+        // Order-0 codeword from the hard decisions on the pivots, held as its difference
+        // from the hard decisions.
+        diff0.copy_from_slice(hard);
+        for r in 0..k {
+            let col = ((pivots[2*r] as usize) << 8) | pivots[2*r + 1] as usize;
+            if get_bit(hard, col) {
+                for (x, g) in diff0.iter_mut().zip(matrix[r*row_len..(r+1)*row_len].iter()) {
+                    *x ^= *g;
+                }
+            }
+        }
+
+        // Try flipping each single pivot bit and each pair, keeping the best candidate
+        let mut best = discrepancy(llrs, diff0);
+        let mut best_flips = (k, k);
+        if order >= 1 {
+            for r in 0..k {
+                let row_r = &matrix[r*row_len..(r+1)*row_len];
+                for ((d, d0), g) in diff.iter_mut().zip(diff0.iter()).zip(row_r.iter()) {
+                    *d = *d0 ^ *g;
+                }
+                let score = discrepancy(llrs, diff);
+                if score < best {
+                    best = score;
+                    best_flips = (r, k);
+                }
+                if order >= 2 {
+                    for s in (r+1)..k {
+                        let row_s = &matrix[s*row_len..(s+1)*row_len];
+                        let score = discrepancy_xor(llrs, diff, row_s);
+                        if score < best {
+                            best = score;
+                            best_flips = (r, s);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Codeword is the hard decisions plus the chosen difference
+        for ((o, h), d) in output.iter_mut().zip(hard.iter()).zip(diff0.iter()) {
+            *o = *h ^ *d;
+        }
+        for &r in &[best_flips.0, best_flips.1] {
+            if r < k {
+                for (o, g) in output.iter_mut().zip(matrix[r*row_len..(r+1)*row_len].iter()) {
+                    *o ^= *g;
+                }
+            }
+        }
+
+        best
+    }
+
+    /// Run `decode_ms`, and if it fails, fall back to `decode_osd` of the given `order` using
+    /// the decoder's final marginal LLRs.
+    ///
+    /// Arguments are as for `decode_ms` and `decode_osd`. Returns how the codeword in `output`
+    /// was found, and the number of min-sum iterations run as for `decode_ms`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn decode_ms_osd<T: FloatConvert>(&self, llrs: &[T], output: &mut [u8],
+                                        working: &mut [T], working_u8: &mut [u8],
+                                        osd_working: &mut [u8], maxiters: usize, order: usize)
+        -> (OsdOutcome, usize)
+    {
+        let (success, iters) = self.decode_ms(llrs, output, working, working_u8, maxiters);
+        if success {
+            return (OsdOutcome::MinSum, iters);
+        }
+        self.decode_osd(self.ms_marginals(working), output, osd_working, order);
+        (OsdOutcome::Osd, iters)
+    }
+}
+
+fn get_bit(data: &[u8], i: usize) -> bool {
+    data[i/8] >> (7 - (i%8)) & 1 == 1
+}
+
+/// Sum of |llr| over the bits set in `diff`.
+fn discrepancy<T: FloatConvert>(llrs: &[T], diff: &[u8]) -> f32 {
+    discrepancy_xor(llrs, diff, &[])
+}
+
+/// Sum of |llr| over the bits set in `diff` XOR `row`, where a short `row` is zero-extended.
+pub(crate) fn discrepancy_xor<T: FloatConvert>(llrs: &[T], diff: &[u8], row: &[u8]) -> f32 {
+    let mut sum = 0.0;
+    for (i, d) in diff.iter().enumerate() {
+        let mut x = *d ^ row.get(i).cloned().unwrap_or(0);
+        while x != 0 {
+            let bit = x.leading_zeros() as usize;
+            sum += llrs[i*8 + bit].abs().to_f32();
+            x &= !(0x80 >> bit);
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+
+    use ::codes::LDPCCode;
+    use ::sim::{AwgnChannel, Modulation, Rng};
+    use super::OsdOutcome;
+
+    #[test]
+    fn test_osd_hard() {
+        for &code in &[LDPCCode::TC128, LDPCCode::TC256, LDPCCode::TC512] {
+            let txdata: Vec<u8> = (0..code.k()/8).map(|i| (i * 37 + 1) as u8).collect();
+            let mut txcode = vec![0u8; code.n()/8];
+            code.copy_encode(&txdata, &mut txcode);
+            let mut llrs = vec![0f32; code.n()];
+            code.hard_to_llrs(&txcode, &mut llrs);
+            let mut osd_working = vec![0u8; code.decode_osd_working_len()];
+            let mut output = vec![0u8; code.output_len()];
+
+            // A valid codeword comes straight back out with no discrepancy
+            let d = code.decode_osd(&llrs, &mut output, &mut osd_working, 0);
+            assert_eq!(d, 0.0);
+            assert_eq!(output, txcode);
+
+            // Two confident errors land among the most reliable bits, so order 0 keeps them
+            // but order 2 finds the transmitted codeword
+            for &i in &[3, code.n() - 5] {
+                llrs[i] *= -2.0;
+            }
+            code.decode_osd(&llrs, &mut output, &mut osd_working, 0);
+            assert!(output != txcode);
+            let d = code.decode_osd(&llrs, &mut output, &mut osd_working, 2);
+            assert_eq!(d, 4.0);
+            assert_eq!(output, txcode);
+        }
+    }
+
+    #[test]
+    fn test_ms_osd() {
+        let code = LDPCCode::TC128;
+        let mut ch = AwgnChannel::new(code, Modulation::BPSK, 2.0, 3);
+        let mut rng = Rng::new(4);
+        let mut working = vec![0f32; code.decode_ms_working_len()];
+        let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+        let mut osd_working = vec![0u8; code.decode_osd_working_len()];
+        let mut output = vec![0u8; code.output_len()];
+        let mut correct = [0; 3];
+        let mut osd_frames = 0;
+
+        for _ in 0..200 {
+            let mut txdata = vec![0u8; code.k()/8];
+            rng.fill_bytes(&mut txdata);
+            let mut txcode = vec![0u8; code.n()/8];
+            code.copy_encode(&txdata, &mut txcode);
+            let mut llrs = vec![0f32; code.n()];
+            ch.transmit(&txcode, &mut llrs, 1.0);
+
+            for (order, count) in correct.iter_mut().enumerate() {
+                let (outcome, _) = code.decode_ms_osd(&llrs, &mut output, &mut working,
+                                                      &mut working_u8, &mut osd_working,
+                                                      50, order);
+                if outcome == OsdOutcome::Osd && order == 0 {
+                    osd_frames += 1;
+                }
+                *count += (output == txcode) as usize;
+            }
+        }
+
+        // Some frames needed OSD, and higher orders recover more of them
+        assert!(osd_frames > 0);
+        assert!(correct[1] >= correct[0]);
+        assert!(correct[2] > correct[0]);
+    }
+}
@@ -0,0 +1,256 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! This module provides conversion of floating point channel samples or LLRs into the
+//! fixed-point types used by `decode_ms`.
+//!
+//! A `Quantiser` multiplies each input by a scale factor, rounds it, and clips it to a range.
+//! The range should leave headroom in the output type, since the decoder accumulates several
+//! messages per variable: for `i8`, -32 to 31 is a good choice, and `Quantiser::headroom`
+//! gives the equivalent range for any integer type.
+//!
+//! Rather than picking the scale by hand, `Quantiser::auto` estimates the signal amplitude and
+//! noise variance from a block of BPSK samples, and chooses a scale which converts them to
+//! LLRs and maps nearly all of them into the clipping range without wasting resolution.
+//! Using the same rule everywhere keeps quantisation consistent between receivers.
+//!
+//! ```
+//! # use labrador_ldpc::LDPCCode;
+//! use labrador_ldpc::quantise::Quantiser;
+//!
+//! let code = LDPCCode::TC128;
+//! let txdata: Vec<u8> = (0..8).collect();
+//! let mut txcode = vec![0u8; code.n()/8];
+//! code.copy_encode(&txdata, &mut txcode);
+//!
+//! // Received BPSK samples with some amplitude and a little noise
+//! let samples: Vec<f32> = (0..code.n()).map(|i| {
+//!     let s = if txcode[i/8] >> (7 - (i%8)) & 1 == 1 { -0.3 } else { 0.3 };
+//!     s + 0.1 * ((i * 7919 % 13) as f32 / 6.0 - 1.0)
+//! }).collect();
+//!
+//! let q = Quantiser::auto(&samples, -32.0, 31.0);
+//! let mut llrs = vec![0i8; code.n()];
+//! q.quantise(&samples, &mut llrs);
+//!
+//! let mut working = vec![0i8; code.decode_ms_working_len()];
+//! let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+//! let mut rxdata = vec![0u8; code.output_len()];
+//! code.decode_ms(&llrs, &mut rxdata, &mut working, &mut working_u8, 20);
+//! assert_eq!(&rxdata[..8], &txdata[..]);
+//! ```
+
+use ::decoder::{DecodeFrom, FloatConvert};
+use ::math::sqrtf;
+
+/// Rounding mode used when quantising.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub enum Rounding {
+    /// Round to the nearest integer, with halves rounded away from zero.
+    Nearest,
+
+    /// Round towards zero (truncate).
+    TowardZero,
+}
+
+/// Estimated parameters of a BPSK channel.
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub struct ChannelEstimate {
+    /// Signal amplitude.
+    pub amplitude: f32,
+
+    /// Noise variance (per real dimension).
+    pub noise_var: f32,
+}
+
+impl ChannelEstimate {
+    /// Estimate amplitude and noise variance from BPSK `samples`, using the second and fourth
+    /// moment (M2M4) estimator.
+    ///
+    /// This needs no knowledge of the transmitted bits, but becomes less accurate at low SNR
+    /// and for short blocks. If the estimate suggests no signal at all, the amplitude is taken
+    /// as the mean sample magnitude.
+    pub fn from_bpsk(samples: &[f32]) -> ChannelEstimate {
+        assert!(!samples.is_empty(), "samples must not be empty");
+        let n = samples.len() as f32;
+        let mut m1 = 0.0;
+        let mut m2 = 0.0;
+        let mut m4 = 0.0;
+        for x in samples {
+            let x2 = x * x;
+            m1 += DecodeFrom::abs(x);
+            m2 += x2;
+            m4 += x2 * x2;
+        }
+        ChannelEstimate::from_moments(m1 / n, m2 / n, m4 / n)
+    }
+
+    /// Estimate amplitude and noise variance from the mean of |x|, x² and x⁴ over a block of
+    /// BPSK samples, as for `from_bpsk`.
+    pub(crate) fn from_moments(m1: f32, m2: f32, m4: f32) -> ChannelEstimate {
+        // For BPSK in real Gaussian noise, M2 = S + N and M4 = S^2 + 6SN + 3N^2
+        let s = sqrtf(0.5 * (3.0 * m2 * m2 - m4));
+        if s > 0.0 {
+            let noise_var = if m2 > s { m2 - s } else { 0.0 };
+            ChannelEstimate { amplitude: sqrtf(s), noise_var }
+        } else {
+            ChannelEstimate { amplitude: m1, noise_var: m2 - m1 * m1 }
+        }
+    }
+
+    /// Get the factor which converts a sample to an exact LLR, 2A/σ².
+    ///
+    /// Returns infinity if the noise variance is zero.
+    pub fn llr_factor(&self) -> f32 {
+        2.0 * self.amplitude / self.noise_var
+    }
+}
+
+/// Converts `f32` samples or LLRs into a decoder type with a given scale, range and rounding.
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub struct Quantiser {
+    /// Factor each input is multiplied by before rounding.
+    pub scale: f32,
+
+    /// Smallest output value; lower values are clipped to this.
+    pub min: f32,
+
+    /// Largest output value; higher values are clipped to this.
+    pub max: f32,
+
+    /// Rounding mode.
+    pub rounding: Rounding,
+}
+
+impl Quantiser {
+    /// Create a new quantiser which rounds to nearest.
+    pub fn new(scale: f32, min: f32, max: f32) -> Quantiser {
+        assert!(min < max, "min must be less than max");
+        Quantiser { scale, min, max, rounding: Rounding::Nearest }
+    }
+
+    /// Create a new quantiser for the integer type `T`, with a range one quarter of the full
+    /// range of `T` (for example -32 to 31 for `i8`), leaving headroom for the decoder.
+    pub fn headroom<T: FloatConvert>(scale: f32) -> Quantiser {
+        let max = (T::maxval().to_f32() + 1.0) / 4.0;
+        Quantiser::new(scale, -max, max - 1.0)
+    }
+
+    /// Create a quantiser which turns samples from a BPSK channel with the given `estimate`
+    /// into LLRs spanning the range `min` to `max`.
+    ///
+    /// The scale is chosen so that an LLR two standard deviations above the mean for a
+    /// correctly received bit maps to the smaller of `-min` and `max`; only around 2% of
+    /// samples are clipped. If there is no noise, samples of the estimated amplitude map to
+    /// the limit instead.
+    pub fn from_estimate(estimate: ChannelEstimate, min: f32, max: f32) -> Quantiser {
+        let limit = if -min < max { -min } else { max };
+        let a = estimate.amplitude;
+        let scale = if estimate.noise_var > 0.0 {
+            // LLRs of correctly received bits have mean mu = 2A²/σ² and variance 2mu.
+            let factor = estimate.llr_factor();
+            let mu = a * factor;
+            factor * limit / (mu + 2.0 * sqrtf(2.0 * mu))
+        } else if a > 0.0 {
+            limit / a
+        } else {
+            1.0
+        };
+        Quantiser::new(scale, min, max)
+    }
+
+    /// Create a quantiser for BPSK `samples`, estimating the channel from the samples
+    /// themselves. See `ChannelEstimate::from_bpsk` and `from_estimate`.
+    pub fn auto(samples: &[f32], min: f32, max: f32) -> Quantiser {
+        Quantiser::from_estimate(ChannelEstimate::from_bpsk(samples), min, max)
+    }
+
+    /// Set the rounding mode.
+    pub fn with_rounding(mut self, rounding: Rounding) -> Quantiser {
+        self.rounding = rounding;
+        self
+    }
+
+    /// Quantise a single value.
+    pub fn quantise_one<T: FloatConvert>(&self, x: f32) -> T {
+        let y = x * self.scale;
+        let y = if y.is_nan() {
+            0.0
+        } else if y < self.min {
+            self.min
+        } else if y > self.max {
+            self.max
+        } else {
+            y
+        };
+        let y = match self.rounding {
+            Rounding::Nearest => if y >= 0.0 { y + 0.5 } else { y - 0.5 },
+            Rounding::TowardZero => y,
+        };
+        // The clip range may exceed an i32 for wide types, which only saturates harmlessly
+        T::from_f32(y as i64 as f32)
+    }
+
+    /// Quantise every value in `input` into `output`, which must be the same length.
+    pub fn quantise<T: FloatConvert>(&self, input: &[f32], output: &mut [T]) {
+        assert_eq!(input.len(), output.len(), "output.len() != input.len()");
+        for (x, y) in input.iter().zip(output.iter_mut()) {
+            *y = self.quantise_one(*x);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+
+    use ::sim::Rng;
+    use super::{Quantiser, Rounding, ChannelEstimate};
+
+    #[test]
+    fn test_quantise() {
+        let q = Quantiser::new(10.0, -32.0, 31.0);
+        let input = [0.0, 0.04, 0.05, -0.05, 1.26, -1.26, 5.0, -5.0];
+        let mut output = [0i8; 8];
+        q.quantise(&input, &mut output);
+        assert_eq!(output, [0, 0, 1, -1, 13, -13, 31, -32]);
+
+        let q = q.with_rounding(Rounding::TowardZero);
+        q.quantise(&input, &mut output);
+        assert_eq!(output, [0, 0, 0, 0, 12, -12, 31, -32]);
+
+        let q = Quantiser::headroom::<i8>(1.0);
+        assert_eq!((q.min, q.max), (-32.0, 31.0));
+        let q = Quantiser::headroom::<i16>(1.0);
+        assert_eq!((q.min, q.max), (-8192.0, 8191.0));
+        let mut output = [0i16; 2];
+        q.quantise(&[1e9, -1e9], &mut output);
+        assert_eq!(output, [8191, -8192]);
+    }
+
+    #[test]
+    fn test_estimate() {
+        let mut rng = Rng::new(1);
+        let (a, sigma) = (0.5, 0.25);
+        let samples: Vec<f32> = (0..20000).map(|_| {
+            let s = if rng.next_u64() & 1 == 1 { a } else { -a };
+            (s + sigma * rng.gaussian()) as f32
+        }).collect();
+        let est = ChannelEstimate::from_bpsk(&samples);
+        assert!((est.amplitude - 0.5).abs() < 0.01);
+        assert!((est.noise_var - 0.0625).abs() < 0.005);
+
+        // Very few samples are clipped by the automatic scale
+        let q = Quantiser::from_estimate(est, -32.0, 31.0);
+        let mut llrs = vec![0i8; samples.len()];
+        q.quantise(&samples, &mut llrs);
+        let clipped = llrs.iter().filter(|&&x| x == 31 || x == -32).count();
+        assert!(clipped > 0 && clipped < samples.len() / 20);
+
+        // Noiseless samples map to the limit
+        let est = ChannelEstimate::from_bpsk(&[1.0, -1.0, 1.0, 1.0]);
+        assert_eq!(est.noise_var, 0.0);
+        let q = Quantiser::from_estimate(est, -32.0, 31.0);
+        assert_eq!(q.quantise_one::<i8>(-1.0), -31);
+    }
+}
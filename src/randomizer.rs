@@ -0,0 +1,170 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! This module provides the CCSDS pseudo-randomizers which are applied to codewords before
+//! transmission to ensure sufficient bit transitions.
+//!
+//! Two sequences are available:
+//!
+//! * `Randomizer::TM` is the telemetry randomizer from CCSDS 131.0-B, generated by
+//!   h(x) = x^8 + x^7 + x^5 + x^3 + 1, and is applied to the whole TM codeword.
+//! * `Randomizer::TC` is the telecommand randomizer from CCSDS 231.0-B, generated by
+//!   h(x) = x^8 + x^6 + x^4 + x^3 + x^2 + x + 1, and is applied to the TC transfer frame.
+//!
+//! Both sequences start from an all-ones state and repeat every 255 bits. Randomizing is an XOR
+//! with the sequence so applying it a second time removes it again. For soft information,
+//! `apply_llrs` flips the sign of every LLR where the sequence is 1, so randomization can be
+//! removed before calling `decode_ms`.
+//!
+//! ```
+//! # use labrador_ldpc::LDPCCode;
+//! use labrador_ldpc::randomizer::Randomizer;
+//!
+//! let code = LDPCCode::TC128;
+//! let txdata: Vec<u8> = (0..8).collect();
+//! let mut txcode = vec![0u8; code.n()/8];
+//! code.copy_encode(&txdata, &mut txcode);
+//!
+//! // Randomize before transmission
+//! Randomizer::TM.apply(&mut txcode);
+//!
+//! // Receive soft information and remove the randomization
+//! let mut llrs = vec![0i8; code.n()];
+//! code.hard_to_llrs(&txcode, &mut llrs);
+//! Randomizer::TM.apply_llrs(&mut llrs);
+//!
+//! let mut working = vec![0i8; code.decode_ms_working_len()];
+//! let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+//! let mut rxdata = vec![0u8; code.output_len()];
+//! code.decode_ms(&llrs, &mut rxdata, &mut working, &mut working_u8, 20);
+//! assert_eq!(&rxdata[..8], &txdata[..]);
+//! ```
+
+use ::decoder::{DecodeFrom, saturating_neg};
+
+/// Available CCSDS pseudo-randomizer sequences.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub enum Randomizer {
+    /// CCSDS 131.0-B telemetry randomizer, h(x) = x^8 + x^7 + x^5 + x^3 + 1.
+    TM,
+
+    /// CCSDS 231.0-B telecommand randomizer, h(x) = x^8 + x^6 + x^4 + x^3 + x^2 + x + 1.
+    TC,
+}
+
+/// Iterator over the bytes of a randomizer sequence.
+///
+/// Each byte holds the next 8 bits of the sequence, MSB first. The iterator never ends;
+/// the sequence repeats every 255 bits.
+///
+/// `Sequence` is obtained from `Randomizer::sequence()`.
+#[derive(Clone,Debug)]
+pub struct Sequence {
+    state: u8,
+    taps: u8,
+}
+
+impl Iterator for Sequence {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        // The state register holds the next 8 output bits, oldest in the MSB.
+        // Each new bit is the parity of the tapped bits.
+        let mut byte = 0;
+        for _ in 0..8 {
+            byte = (byte << 1) | (self.state >> 7);
+            let feedback = (self.state & self.taps).count_ones() as u8 & 1;
+            self.state = (self.state << 1) | feedback;
+        }
+        Some(byte)
+    }
+}
+
+impl Randomizer {
+    /// Get an iterator over the bytes of this randomizer's sequence, from the start.
+    pub fn sequence(&self) -> Sequence {
+        let taps = match *self {
+            Randomizer::TM => 0x95,
+            Randomizer::TC => 0xFA,
+        };
+        Sequence { state: 0xFF, taps }
+    }
+
+    /// Apply (or remove) the randomizer to hard information in `data`.
+    ///
+    /// The sequence starts from the beginning at `data[0]`.
+    pub fn apply(&self, data: &mut [u8]) {
+        for (x, r) in data.iter_mut().zip(self.sequence()) {
+            *x ^= r;
+        }
+    }
+
+    /// Apply (or remove) the randomizer to soft information in `llrs`.
+    ///
+    /// Every LLR corresponding to a 1 in the sequence has its sign flipped.
+    /// The sequence starts from the beginning at `llrs[0]`.
+    ///
+    /// Negation saturates: an LLR below `-T::maxval()`, such as `i8::MIN`, becomes
+    /// `T::maxval()`, since its exact negation is not representable.
+    pub fn apply_llrs<T: DecodeFrom>(&self, llrs: &mut [T]) {
+        for (chunk, r) in llrs.chunks_mut(8).zip(self.sequence()) {
+            for (i, llr) in chunk.iter_mut().enumerate() {
+                if r >> (7 - i) & 1 == 1 {
+                    *llr = saturating_neg(*llr);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+
+    use super::Randomizer;
+
+    #[test]
+    fn test_sequences() {
+        let tm: Vec<u8> = Randomizer::TM.sequence().take(8).collect();
+        assert_eq!(tm, vec![0xFF, 0x48, 0x0E, 0xC0, 0x9A, 0x0D, 0x70, 0xBC]);
+
+        let tc: Vec<u8> = Randomizer::TC.sequence().take(5).collect();
+        assert_eq!(tc, vec![0xFF, 0x39, 0x9E, 0x5A, 0x68]);
+
+        // Both sequences repeat every 255 bits, so 255 bytes later we're back at the start
+        for r in &[Randomizer::TM, Randomizer::TC] {
+            let seq: Vec<u8> = r.sequence().take(256).collect();
+            assert_eq!(seq[255], seq[0]);
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let orig: Vec<u8> = (0..100).collect();
+        let mut data = orig.clone();
+        Randomizer::TC.apply(&mut data);
+        assert_ne!(data, orig);
+        Randomizer::TC.apply(&mut data);
+        assert_eq!(data, orig);
+    }
+
+    #[test]
+    fn test_apply_llrs() {
+        let mut data = vec![0u8; 32];
+        Randomizer::TM.apply(&mut data);
+        let mut llrs = vec![1.0f32; 32*8];
+        Randomizer::TM.apply_llrs(&mut llrs);
+        for (i, llr) in llrs.iter().enumerate() {
+            let bit = data[i/8] >> (7 - (i%8)) & 1;
+            assert_eq!(*llr, if bit == 1 { -1.0 } else { 1.0 });
+        }
+
+        // The most negative integer LLRs saturate rather than overflowing
+        let mut llrs = vec![i8::MIN; 8];
+        Randomizer::TM.apply_llrs(&mut llrs);
+        assert_eq!(llrs, vec![i8::MAX; 8]);
+        let mut llrs = vec![i16::MIN, i16::MAX, -1, 0, 1, i16::MIN, i16::MIN, i16::MIN];
+        Randomizer::TM.apply_llrs(&mut llrs);
+        assert_eq!(llrs, vec![i16::MAX, -i16::MAX, 1, 0, -1, i16::MAX, i16::MAX, i16::MAX]);
+    }
+}
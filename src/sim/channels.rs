@@ -0,0 +1,297 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! This module provides hard-decision channel models, for testing `decode_bf` and hard-input
+//! use of `decode_ms`.
+//!
+//! * `BinarySymmetricChannel` flips each bit independently with a fixed probability.
+//! * `GilbertElliottChannel` switches between a good and a bad state with their own bit error
+//!   probabilities, producing bursts of errors.
+//! * `ErasureChannel` erases each bit independently, replacing it with a random value and
+//!   recording its position in an erasure mask.
+//!
+//! Each channel corrupts a codeword in place and returns the number of bits affected.
+//! Erasure masks hold one bit per codeword bit, MSB first, set where the bit is erased.
+//! `punctured_mask` produces the mask for a code's punctured bits, and `erasures_to_llrs`
+//! turns hard bits and a mask into LLRs for `decode_ms`, with erased bits set to zero.
+//!
+//! ```
+//! # use labrador_ldpc::LDPCCode;
+//! use labrador_ldpc::sim::channels::{ErasureChannel, erasures_to_llrs};
+//!
+//! let code = LDPCCode::TC256;
+//! let txdata: Vec<u8> = (0..code.k()/8).map(|i| i as u8).collect();
+//! let mut rxcode = vec![0u8; code.n()/8];
+//! code.copy_encode(&txdata, &mut rxcode);
+//!
+//! let mut channel = ErasureChannel::new(0.05, 1);
+//! let mut mask = vec![0u8; code.n()/8];
+//! channel.corrupt(&mut rxcode, &mut mask);
+//!
+//! let mut llrs = vec![0i8; code.n()];
+//! erasures_to_llrs(&rxcode, &mask, &mut llrs);
+//!
+//! let mut working = vec![0i8; code.decode_ms_working_len()];
+//! let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+//! let mut rxdata = vec![0u8; code.output_len()];
+//! code.decode_ms(&llrs, &mut rxdata, &mut working, &mut working_u8, 50);
+//! assert_eq!(&rxdata[..code.k()/8], &txdata[..]);
+//! ```
+
+use ::codes::LDPCCode;
+use ::decoder::DecodeFrom;
+use super::Rng;
+
+/// Binary symmetric channel.
+#[derive(Clone,Debug)]
+pub struct BinarySymmetricChannel {
+    p: f64,
+    rng: Rng,
+}
+
+impl BinarySymmetricChannel {
+    /// Create a new channel which flips each bit with probability `p`, using a random number
+    /// generator seeded with `seed`.
+    pub fn new(p: f64, seed: u64) -> BinarySymmetricChannel {
+        assert!((0.0..=1.0).contains(&p), "p must be between 0 and 1");
+        BinarySymmetricChannel { p, rng: Rng::new(seed) }
+    }
+
+    /// Flip bits of `data` in place, returning the number of bits flipped.
+    pub fn corrupt(&mut self, data: &mut [u8]) -> usize {
+        let mut flipped = 0;
+        for byte in data.iter_mut() {
+            for bit in 0..8 {
+                if self.rng.uniform() < self.p {
+                    *byte ^= 1 << (7 - bit);
+                    flipped += 1;
+                }
+            }
+        }
+        flipped
+    }
+}
+
+/// Gilbert–Elliott burst error channel.
+///
+/// A two-state Markov chain is advanced once per bit. In the good state bits are flipped with
+/// probability `p_good` and in the bad state with probability `p_bad`. The average burst
+/// (time in the bad state) lasts `1/p_bg` bits, and the long-run fraction of time in the bad
+/// state is `p_gb / (p_gb + p_bg)`.
+#[derive(Clone,Debug)]
+pub struct GilbertElliottChannel {
+    p_gb: f64,
+    p_bg: f64,
+    p_good: f64,
+    p_bad: f64,
+    bad: bool,
+    rng: Rng,
+}
+
+impl GilbertElliottChannel {
+    /// Create a new channel, starting in the good state.
+    ///
+    /// * `p_gb` is the probability of moving from the good to the bad state after each bit
+    /// * `p_bg` is the probability of moving from the bad to the good state after each bit
+    /// * `p_good` and `p_bad` are the bit error probabilities in each state
+    /// * `seed` seeds the random number generator
+    pub fn new(p_gb: f64, p_bg: f64, p_good: f64, p_bad: f64, seed: u64)
+        -> GilbertElliottChannel
+    {
+        for p in &[p_gb, p_bg, p_good, p_bad] {
+            assert!((0.0..=1.0).contains(p), "probabilities must be between 0 and 1");
+        }
+        GilbertElliottChannel { p_gb, p_bg, p_good, p_bad, bad: false, rng: Rng::new(seed) }
+    }
+
+    /// Returns true if the channel is currently in the bad state.
+    pub fn is_bad(&self) -> bool {
+        self.bad
+    }
+
+    /// Flip bits of `data` in place, returning the number of bits flipped.
+    ///
+    /// The channel state carries over between calls, so bursts may span codewords.
+    pub fn corrupt(&mut self, data: &mut [u8]) -> usize {
+        let mut flipped = 0;
+        for byte in data.iter_mut() {
+            for bit in 0..8 {
+                let p = if self.bad { self.p_bad } else { self.p_good };
+                if self.rng.uniform() < p {
+                    *byte ^= 1 << (7 - bit);
+                    flipped += 1;
+                }
+                let p_switch = if self.bad { self.p_bg } else { self.p_gb };
+                if self.rng.uniform() < p_switch {
+                    self.bad = !self.bad;
+                }
+            }
+        }
+        flipped
+    }
+}
+
+/// Random erasure channel.
+#[derive(Clone,Debug)]
+pub struct ErasureChannel {
+    p: f64,
+    rng: Rng,
+}
+
+impl ErasureChannel {
+    /// Create a new channel which erases each bit with probability `p`, using a random number
+    /// generator seeded with `seed`.
+    pub fn new(p: f64, seed: u64) -> ErasureChannel {
+        assert!((0.0..=1.0).contains(&p), "p must be between 0 and 1");
+        ErasureChannel { p, rng: Rng::new(seed) }
+    }
+
+    /// Erase bits of `data` in place, returning the number of bits newly erased.
+    ///
+    /// Erased bits are replaced with random values, as a hard-decision receiver would see, and
+    /// marked in `mask`, which must be the same length as `data`. Bits already set in `mask`
+    /// are left set and not counted again, so it can be initialised with `punctured_mask`.
+    pub fn corrupt(&mut self, data: &mut [u8], mask: &mut [u8]) -> usize {
+        assert_eq!(data.len(), mask.len(), "mask.len() != data.len()");
+        let mut erased = 0;
+        for (byte, m) in data.iter_mut().zip(mask.iter_mut()) {
+            for bit in 0..8 {
+                if self.rng.uniform() < self.p {
+                    let b = 1 << (7 - bit);
+                    *byte = (*byte & !b) | (self.rng.next_u64() as u8 & b);
+                    if *m & b == 0 {
+                        *m |= b;
+                        erased += 1;
+                    }
+                }
+            }
+        }
+        erased
+    }
+}
+
+/// Write the erasure mask for a full codeword of `code` including its punctured bits.
+///
+/// `mask` must be `code.output_len()` bytes long, i.e. (n+p)/8. The n transmitted bits are
+/// cleared and the p punctured bits are set.
+pub fn punctured_mask(code: LDPCCode, mask: &mut [u8]) {
+    assert_eq!(mask.len(), code.output_len(), "mask.len() != (n+p)/8");
+    let n = code.n() / 8;
+    for x in &mut mask[..n] { *x = 0x00 }
+    for x in &mut mask[n..] { *x = 0xFF }
+}
+
+/// Convert hard bits in `data` to LLRs in `llrs`, with zero LLRs for erased bits.
+///
+/// Unerased bits are given LLRs of +-1, as for `hard_to_llrs`. `mask` must be at least as long
+/// as `data`, and `llrs` must have exactly `data.len() * 8` elements.
+pub fn erasures_to_llrs<T: DecodeFrom>(data: &[u8], mask: &[u8], llrs: &mut [T]) {
+    assert!(mask.len() >= data.len(), "mask.len() < data.len()");
+    assert_eq!(llrs.len(), data.len() * 8, "llrs.len() != data.len() * 8");
+    for (i, llr) in llrs.iter_mut().enumerate() {
+        let b = 7 - (i%8);
+        *llr = if mask[i/8] >> b & 1 == 1 {
+            T::zero()
+        } else if data[i/8] >> b & 1 == 1 {
+            -T::one()
+        } else {
+            T::one()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+
+    use ::codes::LDPCCode;
+    use super::{BinarySymmetricChannel, GilbertElliottChannel, ErasureChannel,
+                punctured_mask, erasures_to_llrs};
+
+    fn count_ones(data: &[u8]) -> usize {
+        data.iter().map(|x| x.count_ones() as usize).sum()
+    }
+
+    #[test]
+    fn test_bsc() {
+        let mut channel = BinarySymmetricChannel::new(0.1, 7);
+        let mut data = vec![0u8; 10000];
+        let flipped = channel.corrupt(&mut data);
+        assert_eq!(flipped, count_ones(&data));
+        assert!(flipped > 7500 && flipped < 8500);
+
+        // decode_bf corrects a few scattered errors
+        let code = LDPCCode::TM2048;
+        let mut channel = BinarySymmetricChannel::new(0.002, 3);
+        let txdata: Vec<u8> = (0..code.k()/8).map(|i| i as u8).collect();
+        let mut rxcode = vec![0u8; code.n()/8];
+        code.copy_encode(&txdata, &mut rxcode);
+        assert!(channel.corrupt(&mut rxcode) > 0);
+        let mut working = vec![0u8; code.decode_bf_working_len()];
+        let mut output = vec![0u8; code.output_len()];
+        let (success, _) = code.decode_bf(&rxcode, &mut output, &mut working, 50);
+        assert!(success);
+        assert_eq!(&output[..code.k()/8], &txdata[..]);
+    }
+
+    #[test]
+    fn test_gilbert_elliott() {
+        // Bad 1/11 of the time with bursts averaging 10 bits, half of which are errors
+        let mut channel = GilbertElliottChannel::new(0.01, 0.1, 0.0, 0.5, 5);
+        assert!(!channel.is_bad());
+        let mut data = vec![0u8; 20000];
+        let flipped = channel.corrupt(&mut data);
+        assert_eq!(flipped, count_ones(&data));
+        let expected = 20000.0 * 8.0 * 0.5 / 11.0;
+        assert!((flipped as f64 - expected).abs() < 0.15 * expected);
+
+        // Errors are clustered: far fewer isolated errors than a BSC at the same rate
+        let mut isolated = 0;
+        for i in 1..(data.len()*8 - 1) {
+            let bit = |j: usize| data[j/8] >> (7 - (j%8)) & 1;
+            if bit(i) == 1 && bit(i - 1) == 0 && bit(i + 1) == 0 {
+                isolated += 1;
+            }
+        }
+        assert!(isolated < flipped / 2);
+    }
+
+    #[test]
+    fn test_erasures() {
+        let code = LDPCCode::TM1280;
+        let mut mask = vec![0u8; code.output_len()];
+        punctured_mask(code, &mut mask);
+        assert_eq!(count_ones(&mask), code.punctured_bits());
+        assert_eq!(mask[code.n()/8 - 1], 0);
+
+        let mut channel = ErasureChannel::new(0.2, 11);
+        let mut data = vec![0u8; code.n()/8];
+        let erased_first = channel.corrupt(&mut data, &mut mask[..code.n()/8]);
+        assert_eq!(erased_first + code.punctured_bits(), count_ones(&mask));
+
+        // Bits already marked, such as punctured bits, are not counted again
+        let erased = channel.corrupt(&mut data, &mut mask[..code.n()/8]);
+        assert!(erased > 0);
+        assert!(count_ones(&mask) < code.n() + code.punctured_bits());
+        assert_eq!(erased + erased_first + code.punctured_bits(), count_ones(&mask));
+        let mut full_mask = vec![0u8; code.output_len()];
+        punctured_mask(code, &mut full_mask);
+        let mut full_data = vec![0u8; code.output_len()];
+        assert_eq!(ErasureChannel::new(1.0, 1).corrupt(&mut full_data, &mut full_mask), code.n());
+
+        // Only erased bits may have changed
+        for (x, m) in data.iter().zip(mask.iter()) {
+            assert_eq!(x & !m, 0);
+        }
+
+        let mut llrs = vec![0i8; code.n()];
+        erasures_to_llrs(&data, &mask, &mut llrs);
+        for (i, llr) in llrs.iter().enumerate() {
+            if mask[i/8] >> (7 - (i%8)) & 1 == 1 {
+                assert_eq!(*llr, 0);
+            } else {
+                assert_eq!(*llr, 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,337 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! This module provides a channel simulator for evaluating the codes, and requires the `std`
+//! feature.
+//!
+//! It contains a small deterministic pseudo-random number generator, so simulations are
+//! reproducible from a seed without any external dependencies, along with BPSK and QPSK
+//! modulation and an additive white Gaussian noise channel parameterised by Eb/N0.
+//!
+//! The channel accounts for the code rate k/n, so punctured bits (which are never transmitted)
+//! are correctly treated as carrying no energy. Received symbols are converted to LLRs of any
+//! built-in decoder type (see `decoder::FloatConvert`), with an extra `scale` factor to make
+//! best use of integer types; values which don't fit are saturated.
+//!
+//! Hard-decision channel models (binary symmetric, burst and erasure channels) are in the
+//! [`channels`](channels/index.html) submodule.
+//!
+//! ```
+//! # use labrador_ldpc::LDPCCode;
+//! use labrador_ldpc::sim::{AwgnChannel, Modulation};
+//!
+//! let code = LDPCCode::TM1280;
+//! let mut channel = AwgnChannel::new(code, Modulation::BPSK, 4.0, 1234);
+//!
+//! let txdata: Vec<u8> = (0..code.k()/8).map(|i| i as u8).collect();
+//! let mut txcode = vec![0u8; code.n()/8];
+//! code.copy_encode(&txdata, &mut txcode);
+//!
+//! // Transmit over the channel into i8 LLRs. At this noise level they are mostly within
+//! // +-16, leaving plenty of headroom for the decoder.
+//! let mut llrs = vec![0i8; code.n()];
+//! channel.transmit(&txcode, &mut llrs, 1.0);
+//!
+//! let mut working = vec![0i8; code.decode_ms_working_len()];
+//! let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+//! let mut rxdata = vec![0u8; code.output_len()];
+//! let (success, _) = code.decode_ms(&llrs, &mut rxdata, &mut working, &mut working_u8, 50);
+//! assert!(success);
+//! assert_eq!(&rxdata[..code.k()/8], &txdata[..]);
+//! ```
+
+use std::prelude::v1::*;
+
+use ::codes::LDPCCode;
+use ::decoder::FloatConvert;
+
+pub mod channels;
+
+/// Deterministic pseudo-random number generator.
+///
+/// This is xoshiro256**, seeded with splitmix64. It is fast and has good statistical
+/// properties, but is not suitable for cryptographic use.
+#[derive(Clone,Debug)]
+pub struct Rng {
+    s: [u64; 4],
+    spare: Option<f64>,
+}
+
+impl Rng {
+    /// Create a new generator from `seed`. The same seed always gives the same sequence.
+    pub fn new(seed: u64) -> Rng {
+        let mut x = seed;
+        let mut s = [0u64; 4];
+        for si in &mut s {
+            x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            *si = z ^ (z >> 31);
+        }
+        Rng { s, spare: None }
+    }
+
+    /// Get the next 64 random bits.
+    pub fn next_u64(&mut self) -> u64 {
+        let result = self.s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.s[1] << 17;
+        self.s[2] ^= self.s[0];
+        self.s[3] ^= self.s[1];
+        self.s[1] ^= self.s[2];
+        self.s[0] ^= self.s[3];
+        self.s[2] ^= t;
+        self.s[3] = self.s[3].rotate_left(45);
+        result
+    }
+
+    /// Get a uniformly distributed number in [0, 1).
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+
+    /// Get a normally distributed number with zero mean and unit variance.
+    pub fn gaussian(&mut self) -> f64 {
+        if let Some(x) = self.spare.take() {
+            return x;
+        }
+
+        // Box-Muller transform, generating two values at a time
+        let u1 = 1.0 - self.uniform();
+        let u2 = self.uniform();
+        let r = (-2.0 * u1.ln()).sqrt();
+        let theta = 2.0 * ::core::f64::consts::PI * u2;
+        self.spare = Some(r * theta.sin());
+        r * theta.cos()
+    }
+
+    /// Fill `data` with random bytes.
+    pub fn fill_bytes(&mut self, data: &mut [u8]) {
+        for chunk in data.chunks_mut(8) {
+            let x = self.next_u64().to_be_bytes();
+            chunk.copy_from_slice(&x[..chunk.len()]);
+        }
+    }
+}
+
+/// Available modulation schemes.
+///
+/// Both map a 0 bit to a positive amplitude, matching the LLR sign convention, and have unit
+/// energy per symbol.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub enum Modulation {
+    /// Binary phase shift keying, one bit per symbol on the in-phase axis.
+    BPSK,
+
+    /// Gray-coded quadrature phase shift keying, two bits per symbol, with the first bit on
+    /// the in-phase axis and the second on the quadrature axis.
+    QPSK,
+}
+
+impl Modulation {
+    /// Get the number of bits carried by each symbol.
+    pub fn bits_per_symbol(&self) -> usize {
+        match *self {
+            Modulation::BPSK => 1,
+            Modulation::QPSK => 2,
+        }
+    }
+
+    /// Amplitude on each axis carrying a bit.
+    fn amplitude(&self) -> f64 {
+        match *self {
+            Modulation::BPSK => 1.0,
+            Modulation::QPSK => ::core::f64::consts::FRAC_1_SQRT_2,
+        }
+    }
+
+    /// Modulate the bits in `data` (MSB first) into `symbols`, as (in-phase, quadrature)
+    /// pairs.
+    ///
+    /// `symbols` must be exactly `data.len() * 8 / bits_per_symbol()` long.
+    pub fn modulate(&self, data: &[u8], symbols: &mut [(f32, f32)]) {
+        let bps = self.bits_per_symbol();
+        assert_eq!(symbols.len() * bps, data.len() * 8, "symbols.len() incorrect");
+        let a = self.amplitude() as f32;
+        let amp = |bit: usize| if data[bit/8] >> (7 - (bit%8)) & 1 == 1 { -a } else { a };
+        for (i, symbol) in symbols.iter_mut().enumerate() {
+            *symbol = match *self {
+                Modulation::BPSK => (amp(i), 0.0),
+                Modulation::QPSK => (amp(2*i), amp(2*i + 1)),
+            };
+        }
+    }
+}
+
+/// Additive white Gaussian noise channel.
+#[derive(Clone,Debug)]
+pub struct AwgnChannel {
+    modulation: Modulation,
+    sigma: f64,
+    rng: Rng,
+}
+
+impl AwgnChannel {
+    /// Create a new channel for transmitting codewords of `code` using `modulation` at the
+    /// given Eb/N0 in dB, using a random number generator seeded with `seed`.
+    ///
+    /// Eb is the energy per information bit, so the noise level accounts for both the code
+    /// rate k/n and the number of bits per symbol.
+    pub fn new(code: LDPCCode, modulation: Modulation, ebn0_db: f64, seed: u64)
+        -> AwgnChannel
+    {
+        let rate = code.k() as f64 / code.n() as f64;
+        let ebn0 = 10.0f64.powf(ebn0_db / 10.0);
+        let esn0 = ebn0 * rate * modulation.bits_per_symbol() as f64;
+        let sigma = (1.0 / (2.0 * esn0)).sqrt();
+        AwgnChannel { modulation, sigma, rng: Rng::new(seed) }
+    }
+
+    /// Get the modulation in use.
+    pub fn modulation(&self) -> Modulation {
+        self.modulation
+    }
+
+    /// Get the standard deviation of the noise on each axis.
+    pub fn sigma(&self) -> f64 {
+        self.sigma
+    }
+
+    /// Get the noise power spectral density N0, relative to unit symbol energy.
+    pub fn n0(&self) -> f64 {
+        2.0 * self.sigma * self.sigma
+    }
+
+    /// Get the random number generator, for example to generate random messages.
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    /// Add noise to `symbols` in place.
+    pub fn add_noise(&mut self, symbols: &mut [(f32, f32)]) {
+        for symbol in symbols.iter_mut() {
+            symbol.0 += (self.sigma * self.rng.gaussian()) as f32;
+            if self.modulation != Modulation::BPSK {
+                symbol.1 += (self.sigma * self.rng.gaussian()) as f32;
+            }
+        }
+    }
+
+    /// Convert received `symbols` into LLRs.
+    ///
+    /// The exact LLRs are multiplied by `scale` before conversion into `T`, saturating if they
+    /// are out of range. `llrs` must be exactly `symbols.len() * bits_per_symbol()` long.
+    pub fn demodulate<T: FloatConvert>(&self, symbols: &[(f32, f32)], llrs: &mut [T],
+                                     scale: f32)
+    {
+        let bps = self.modulation.bits_per_symbol();
+        assert_eq!(llrs.len(), symbols.len() * bps, "llrs.len() incorrect");
+        let factor = (2.0 * self.modulation.amplitude() / (self.sigma * self.sigma)) as f32
+                     * scale;
+        for (chunk, symbol) in llrs.chunks_mut(bps).zip(symbols.iter()) {
+            chunk[0] = T::from_f32(factor * symbol.0);
+            if bps == 2 {
+                chunk[1] = T::from_f32(factor * symbol.1);
+            }
+        }
+    }
+
+    /// Modulate `codeword`, add noise, and demodulate into `llrs`, scaled by `scale`.
+    ///
+    /// `llrs` must be exactly `codeword.len() * 8` long.
+    pub fn transmit<T: FloatConvert>(&mut self, codeword: &[u8], llrs: &mut [T], scale: f32) {
+        let mut symbols = vec![(0.0, 0.0); codeword.len() * 8 / self.modulation.bits_per_symbol()];
+        self.modulation.modulate(codeword, &mut symbols);
+        self.add_noise(&mut symbols);
+        self.demodulate(&symbols, llrs, scale);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+
+    use ::codes::LDPCCode;
+    use super::{Rng, Modulation, AwgnChannel};
+
+    #[test]
+    fn test_rng() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let mut c = Rng::new(43);
+        let xa: Vec<u64> = (0..10).map(|_| a.next_u64()).collect();
+        let xb: Vec<u64> = (0..10).map(|_| b.next_u64()).collect();
+        let xc: Vec<u64> = (0..10).map(|_| c.next_u64()).collect();
+        assert_eq!(xa, xb);
+        assert_ne!(xa, xc);
+
+        let n = 100000;
+        let samples: Vec<f64> = (0..n).map(|_| a.gaussian()).collect();
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        let var = samples.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / n as f64;
+        assert!(mean.abs() < 0.02);
+        assert!((var - 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_modulate() {
+        let mut symbols = vec![(0.0, 0.0); 8];
+        Modulation::BPSK.modulate(&[0b1010_0000], &mut symbols);
+        assert_eq!(symbols[0], (-1.0, 0.0));
+        assert_eq!(symbols[1], (1.0, 0.0));
+        assert_eq!(symbols[2], (-1.0, 0.0));
+
+        let mut symbols = vec![(0.0, 0.0); 4];
+        Modulation::QPSK.modulate(&[0b1001_0000], &mut symbols);
+        assert!(symbols[0].0 < 0.0 && symbols[0].1 > 0.0);
+        assert!(symbols[1].0 > 0.0 && symbols[1].1 < 0.0);
+        for s in &symbols {
+            assert!((s.0 * s.0 + s.1 * s.1 - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_noise_level() {
+        // TM2048 is rate 1/2, so at Eb/N0 = 0dB with BPSK, Es/N0 = -3dB and sigma^2 = 1
+        let channel = AwgnChannel::new(LDPCCode::TM2048, Modulation::BPSK, 0.0, 0);
+        assert!((channel.sigma() - 1.0).abs() < 1e-9);
+        let channel = AwgnChannel::new(LDPCCode::TM2048, Modulation::QPSK, 0.0, 0);
+        assert!((channel.n0() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_llr_saturation() {
+        let channel = AwgnChannel::new(LDPCCode::TC128, Modulation::BPSK, 10.0, 0);
+        let symbols = [(1.0, 0.0), (-1.0, 0.0), (0.001, 0.0)];
+        let mut llrs = [0i8; 3];
+        channel.demodulate(&symbols, &mut llrs, 10.0);
+        assert_eq!(llrs, [127, -127, 0]);
+        let mut llrs = [0f32; 3];
+        channel.demodulate(&symbols, &mut llrs, 10.0);
+        assert!(llrs[0] > 127.0 && llrs[1] < -127.0);
+    }
+
+    #[test]
+    fn test_transmit_decode() {
+        for modulation in &[Modulation::BPSK, Modulation::QPSK] {
+            for code in &[LDPCCode::TC256, LDPCCode::TM2048] {
+                let mut channel = AwgnChannel::new(*code, *modulation, 5.0, 99);
+                let mut txdata = vec![0u8; code.k()/8];
+                channel.rng().fill_bytes(&mut txdata);
+                let mut txcode = vec![0u8; code.n()/8];
+                code.copy_encode(&txdata, &mut txcode);
+
+                let mut llrs = vec![0i16; code.n()];
+                channel.transmit(&txcode, &mut llrs, 8.0);
+
+                let mut working = vec![0i16; code.decode_ms_working_len()];
+                let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+                let mut rxdata = vec![0u8; code.output_len()];
+                let (success, _) = code.decode_ms(&llrs, &mut rxdata, &mut working,
+                                                  &mut working_u8, 50);
+                assert!(success);
+                assert_eq!(&rxdata[..code.k()/8], &txdata[..]);
+            }
+        }
+    }
+}
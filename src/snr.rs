@@ -0,0 +1,234 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! This module estimates channel quality from received LLRs, so link telemetry can come
+//! directly from the decoder.
+//!
+//! After a successful decode, the decoded codeword tells us which bit was sent in every
+//! position. Flipping each received LLR to the sign of its transmitted bit leaves a Gaussian
+//! with mean μ and variance 2μ (in natural LLR units), so the ratio of the squared mean to the
+//! variance gives the SNR regardless of any scale applied to the LLRs, and counting the
+//! disagreeing hard decisions gives the channel bit error rate directly.
+//!
+//! If decoding failed, the transmitted bits are unknown, so the blind moment-based (M2M4)
+//! estimator from [`quantise::ChannelEstimate`](../quantise/struct.ChannelEstimate.html) is
+//! used instead and the bit error rate is predicted from the estimated SNR. This is less
+//! accurate, particularly below about 0dB Es/N0.
+//!
+//! Estimates are for the equivalent BPSK channel, where each symbol carries one bit. For
+//! Gray-coded QPSK, where each symbol carries two such bits, add 3dB to get the symbol Es/N0.
+//!
+//! ```
+//! # use labrador_ldpc::LDPCCode;
+//! use labrador_ldpc::snr::EstimateMethod;
+//!
+//! let code = LDPCCode::TC256;
+//! let txdata: Vec<u8> = (0..code.k()/8).map(|i| i as u8).collect();
+//! let mut txcode = vec![0u8; code.n()/8];
+//! code.copy_encode(&txdata, &mut txcode);
+//!
+//! // Received LLRs with two different magnitudes and a few errors
+//! let mut llrs = vec![0f32; code.n()];
+//! code.hard_to_llrs(&txcode, &mut llrs);
+//! for (i, llr) in llrs.iter_mut().enumerate() {
+//!     *llr *= if i % 16 == 0 { -1.0 } else if i % 2 == 0 { 2.0 } else { 4.0 };
+//! }
+//!
+//! let mut working = vec![0f32; code.decode_ms_working_len()];
+//! let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+//! let mut rxdata = vec![0u8; code.output_len()];
+//! let (success, _, link) = code.decode_ms_link(&llrs, &mut rxdata, &mut working,
+//!                                              &mut working_u8, 20);
+//! assert!(success);
+//! assert_eq!(link.method, EstimateMethod::Decoded);
+//! assert_eq!(link.ber, 1.0 / 16.0);
+//! ```
+
+use core::f32;
+
+use ::codes::LDPCCode;
+use ::decoder::{DecodeFrom, FloatConvert};
+use ::quantise::ChannelEstimate;
+use ::math::{lnf, sqrtf, erfcf};
+
+/// How a `LinkEstimate` was obtained.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub enum EstimateMethod {
+    /// Compared against a known (decoded) codeword.
+    Decoded,
+
+    /// Estimated blind from the LLR statistics alone.
+    Blind,
+}
+
+/// Estimated quality of the channel a frame was received over.
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub struct LinkEstimate {
+    /// Es/N0 in dB of the equivalent BPSK channel.
+    pub esn0_db: f32,
+
+    /// Channel (pre-decoding) bit error rate. For `EstimateMethod::Decoded` this is the
+    /// measured fraction of received hard decisions in error, otherwise it is predicted
+    /// from `esn0_db`.
+    pub ber: f32,
+
+    /// How the estimate was obtained.
+    pub method: EstimateMethod,
+}
+
+impl LinkEstimate {
+    /// Estimate channel quality by comparing the n received `llrs` against the transmitted
+    /// `codeword`, which must be at least n/8 bytes long.
+    ///
+    /// `codeword` is normally the `output` of a successful decoder, which is always a valid
+    /// codeword; if only the data is known, re-encode it with `copy_encode` first.
+    pub fn from_codeword<T: FloatConvert>(llrs: &[T], codeword: &[u8]) -> LinkEstimate {
+        assert!(!llrs.is_empty(), "llrs must not be empty");
+        assert!(codeword.len() * 8 >= llrs.len(), "codeword shorter than llrs");
+        let n = llrs.len() as f32;
+        let mut sum = 0.0;
+        let mut sum_sq = 0.0;
+        let mut errors = 0;
+        for (i, llr) in llrs.iter().enumerate() {
+            let bit = codeword[i/8] >> (7 - (i%8)) & 1 == 1;
+            let hard = *llr <= T::zero();
+            let x = llr.to_f32();
+            let z = if bit { -x } else { x };
+            sum += z;
+            sum_sq += z * z;
+            errors += (hard != bit) as usize;
+        }
+        let mean = sum / n;
+        let var = sum_sq / n - mean * mean;
+
+        // The sign-corrected LLRs have mean c*mu and variance 2*c²*mu, with mu = 4 Es/N0
+        let esn0 = if mean <= 0.0 {
+            0.0
+        } else if var <= 0.0 {
+            f32::INFINITY
+        } else {
+            mean * mean / (2.0 * var)
+        };
+
+        LinkEstimate {
+            esn0_db: to_db(esn0),
+            ber: errors as f32 / n,
+            method: EstimateMethod::Decoded,
+        }
+    }
+
+    /// Estimate channel quality from the received `llrs` alone.
+    ///
+    /// See `quantise::ChannelEstimate::from_bpsk` for the estimator used.
+    pub fn blind<T: FloatConvert>(llrs: &[T]) -> LinkEstimate {
+        assert!(!llrs.is_empty(), "llrs must not be empty");
+        let n = llrs.len() as f32;
+        let mut m1 = 0.0;
+        let mut m2 = 0.0;
+        let mut m4 = 0.0;
+        for llr in llrs {
+            let x = llr.to_f32();
+            let x2 = x * x;
+            m1 += DecodeFrom::abs(&x);
+            m2 += x2;
+            m4 += x2 * x2;
+        }
+        let est = ChannelEstimate::from_moments(m1 / n, m2 / n, m4 / n);
+
+        let esn0 = if est.noise_var > 0.0 {
+            est.amplitude * est.amplitude / (2.0 * est.noise_var)
+        } else {
+            f32::INFINITY
+        };
+
+        LinkEstimate {
+            esn0_db: to_db(esn0),
+            ber: 0.5 * erfcf(sqrtf(esn0)),
+            method: EstimateMethod::Blind,
+        }
+    }
+}
+
+impl LDPCCode {
+    /// Run `decode_ms` and estimate the channel quality for the frame.
+    ///
+    /// Arguments are as for `decode_ms`. If decoding succeeds the estimate uses the decoded
+    /// codeword, and otherwise the blind estimator. Returns `(decoding success, iters,
+    /// estimate)`.
+    pub fn decode_ms_link<T: FloatConvert>(&self, llrs: &[T], output: &mut [u8],
+                                         working: &mut [T], working_u8: &mut [u8],
+                                         maxiters: usize)
+        -> (bool, usize, LinkEstimate)
+    {
+        let (success, iters) = self.decode_ms(llrs, output, working, working_u8, maxiters);
+        let estimate = if success {
+            LinkEstimate::from_codeword(llrs, output)
+        } else {
+            LinkEstimate::blind(llrs)
+        };
+        (success, iters, estimate)
+    }
+}
+
+fn to_db(x: f32) -> f32 {
+    10.0 * lnf(x) / f32::consts::LN_10
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+
+    use ::codes::LDPCCode;
+    use ::sim::{AwgnChannel, Modulation, Rng};
+    use super::{LinkEstimate, EstimateMethod};
+
+    #[test]
+    fn test_link_estimate() {
+        let code = LDPCCode::TM2048;
+        let ebn0_db = 3.0;
+        let esn0_db = ebn0_db + 10.0 * (code.k() as f32 / code.n() as f32).log10();
+        let ber = bpsk_ber(esn0_db);
+        let mut ch = AwgnChannel::new(code, Modulation::BPSK, ebn0_db as f64, 8);
+        let mut rng = Rng::new(9);
+        let mut working = vec![0f32; code.decode_ms_working_len()];
+        let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+        let mut output = vec![0u8; code.output_len()];
+
+        for _ in 0..5 {
+            let mut txdata = vec![0u8; code.k()/8];
+            rng.fill_bytes(&mut txdata);
+            let mut txcode = vec![0u8; code.n()/8];
+            code.copy_encode(&txdata, &mut txcode);
+            let mut llrs = vec![0f32; code.n()];
+            ch.transmit(&txcode, &mut llrs, 3.0);
+
+            let (success, _, link) = code.decode_ms_link(&llrs, &mut output, &mut working,
+                                                         &mut working_u8, 50);
+            assert!(success);
+            assert_eq!(link.method, EstimateMethod::Decoded);
+            assert!((link.esn0_db - esn0_db).abs() < 0.3);
+            assert!((link.ber - ber).abs() < 0.02);
+
+            let blind = LinkEstimate::blind(&llrs);
+            assert_eq!(blind.method, EstimateMethod::Blind);
+            assert!((blind.esn0_db - esn0_db).abs() < 1.0);
+            assert!((blind.ber - ber).abs() < 0.03);
+        }
+
+        // An all-zero codeword received perfectly has no measurable noise
+        let link = LinkEstimate::from_codeword(&[1i8; 16], &[0u8; 2]);
+        assert_eq!((link.esn0_db, link.ber), (f32::INFINITY, 0.0));
+    }
+
+    /// BPSK bit error rate at the given Es/N0, erfc(sqrt(Es/N0))/2, by numerical integration.
+    fn bpsk_ber(esn0_db: f32) -> f32 {
+        let x = 10.0f64.powf(esn0_db as f64 / 10.0).sqrt();
+        let steps = 100_000;
+        let dt = 10.0 / steps as f64;
+        let tail: f64 = (0..steps).map(|i| {
+            let t = x + (i as f64 + 0.5) * dt;
+            (-t * t).exp() * dt
+        }).sum();
+        (tail / ::std::f64::consts::PI.sqrt()) as f32
+    }
+}
@@ -0,0 +1,326 @@
+// Copyright 2017 Adam Greig
+// Licensed under the MIT license, see LICENSE for details.
+
+//! This module provides post-processing to recover frames where `decode_ms` is caught in a
+//! trapping set, which causes the error floor at high SNR.
+//!
+//! A trapping set is a small group of variables whose wrong values are only checked by a few
+//! parity checks, so the decoder settles on them and leaves just a handful of checks
+//! unsatisfied. When decoding fails with at most `max_unsatisfied` unsatisfied checks,
+//! `decode_ms_trapping` finds every variable connected to those checks and decodes again with
+//! modified LLRs on just those positions: first with all of them erased, and then by pushing
+//! each one in turn firmly towards the opposite of its failed hard decision, least reliable
+//! channel LLR first. Frames with many unsatisfied checks are simply too noisy, so are not
+//! retried.
+//!
+//! ```
+//! # use labrador_ldpc::LDPCCode;
+//! use labrador_ldpc::trapping::TrappingParams;
+//!
+//! let code = LDPCCode::TM1280;
+//! let txdata: Vec<u8> = (0..code.k()/8).map(|i| i as u8).collect();
+//! let mut txcode = vec![0u8; code.n()/8];
+//! code.copy_encode(&txdata, &mut txcode);
+//! let mut llrs = vec![0i16; code.n()];
+//! code.hard_to_llrs(&txcode, &mut llrs);
+//!
+//! let params = TrappingParams::default();
+//! let mut working = vec![0i16; code.decode_ms_working_len()];
+//! let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+//! let mut trapping_working = vec![0i16; code.decode_trapping_working_len()];
+//! let mut trapping_working_u8 = vec![0u8; code.decode_trapping_working_u8_len()];
+//! let mut rxdata = vec![0u8; code.output_len()];
+//! let result = code.decode_ms_trapping(&llrs, &mut rxdata, &mut working, &mut working_u8,
+//!                                      &mut trapping_working, &mut trapping_working_u8,
+//!                                      50, &params);
+//! assert!(result.success);
+//! assert_eq!(result.attempts, 0);
+//! assert_eq!(&rxdata[..code.k()/8], &txdata[..]);
+//! ```
+
+use ::codes::LDPCCode;
+use ::decoder::{FloatConvert, EarlyStopping, StopReason, saturating_neg};
+
+/// Parameters for trapping-set post-processing.
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub struct TrappingParams {
+    /// Only retry frames left with at most this many unsatisfied parity checks.
+    pub max_unsatisfied: usize,
+
+    /// Maximum number of retries, including the initial retry with all participating
+    /// variables erased.
+    pub max_attempts: usize,
+
+    /// Maximum number of min-sum iterations for each retry. Each retry decodes from scratch,
+    /// so this should allow about as many iterations as a frame takes to converge, which for
+    /// the large TM codes near their threshold can be most of `maxiters`.
+    pub retry_iters: usize,
+
+    /// Early stopping criteria for the initial decode, so stalled frames reach
+    /// post-processing sooner. Disabled by default, since stopping too eagerly abandons
+    /// frames which would still have decoded. Retries always run for up to `retry_iters`
+    /// iterations.
+    pub early: EarlyStopping,
+}
+
+impl Default for TrappingParams {
+    fn default() -> TrappingParams {
+        TrappingParams {
+            max_unsatisfied: 8,
+            max_attempts: 16,
+            retry_iters: 50,
+            early: EarlyStopping::default(),
+        }
+    }
+}
+
+/// Result of decoding with trapping-set post-processing.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub struct TrappingResult {
+    /// True if a codeword was found.
+    pub success: bool,
+
+    /// Number of unsatisfied parity checks after the initial decode, or 0 if it succeeded.
+    pub unsatisfied: usize,
+
+    /// Number of retries made.
+    pub attempts: usize,
+
+    /// Total number of min-sum iterations run, over the initial decode and all retries.
+    pub iters: usize,
+}
+
+impl LDPCCode {
+    /// Get the length of [T] required for the working area of `decode_ms_trapping`.
+    ///
+    /// Equal to n.
+    pub fn decode_trapping_working_len(&self) -> usize {
+        self.n()
+    }
+
+    /// Get the length of [u8] required for the u8 working area of `decode_ms_trapping`.
+    ///
+    /// Equal to (n + punctured_bits - k)/8 + 2*(n + punctured_bits)/8.
+    pub fn decode_trapping_working_u8_len(&self) -> usize {
+        self.decode_ms_working_u8_len() + 2 * self.output_len()
+    }
+
+    /// Run the min-sum decoder, and if it fails with only a few unsatisfied parity checks,
+    /// retry with modified LLRs on the variables connected to them.
+    ///
+    /// * `llrs`, `output`, `working`, `working_u8` and `maxiters` are as for `decode_ms`
+    /// * `trapping_working` must have `decode_trapping_working_len()` elements
+    /// * `trapping_working_u8` must have `decode_trapping_working_u8_len()` elements
+    ///
+    /// `output` holds the decoded codeword on success, and otherwise the hard decisions of the
+    /// initial decode.
+    #[allow(clippy::too_many_arguments)]
+    pub fn decode_ms_trapping<T: FloatConvert>(&self, llrs: &[T], output: &mut [u8],
+                                             working: &mut [T], working_u8: &mut [u8],
+                                             trapping_working: &mut [T],
+                                             trapping_working_u8: &mut [u8], maxiters: usize,
+                                             params: &TrappingParams)
+        -> TrappingResult
+    {
+        let n = self.n();
+        assert_eq!(trapping_working.len(), self.decode_trapping_working_len(),
+                   "trapping_working.len() incorrect");
+        assert_eq!(trapping_working_u8.len(), self.decode_trapping_working_u8_len(),
+                   "trapping_working_u8.len() incorrect");
+
+        let (reason, iters) = self.decode_ms_early(llrs, output, working, working_u8, maxiters,
+                                                   &params.early);
+        let mut result = TrappingResult {
+            success: reason == StopReason::Codeword, unsatisfied: 0, attempts: 0, iters,
+        };
+        if result.success {
+            return result;
+        }
+
+        // Find the unsatisfied checks, then every variable connected to one
+        let (checks, rest) = trapping_working_u8.split_at_mut(self.decode_ms_working_u8_len());
+        let (vars, failed) = rest.split_at_mut(self.output_len());
+        for x in checks.iter_mut() { *x = 0 }
+        for (check, var) in self.iter_paritychecks() {
+            if output[var/8] >> (7 - (var%8)) & 1 == 1 {
+                checks[check/8] ^= 1 << (7 - (check%8));
+            }
+        }
+        result.unsatisfied = checks.iter().map(|x| x.count_ones() as usize).sum();
+        if result.unsatisfied > params.max_unsatisfied {
+            return result;
+        }
+        for x in vars.iter_mut() { *x = 0 }
+        for (check, var) in self.iter_paritychecks() {
+            if checks[check/8] >> (7 - (check%8)) & 1 == 1 {
+                vars[var/8] |= 1 << (7 - (var%8));
+            }
+        }
+
+        // Keep the failed hard decisions, since `output` is overwritten by each retry.
+        // Punctured variables have no channel LLR to modify, so only the first n are used.
+        failed.copy_from_slice(output);
+        let magnitude = |llr: T| if llr < T::zero() { saturating_neg(llr) } else { llr };
+        let mut maxllr = T::zero();
+        for &llr in llrs {
+            if magnitude(llr) > maxllr {
+                maxllr = magnitude(llr);
+            }
+        }
+        let modified = trapping_working;
+        while result.attempts < params.max_attempts {
+            modified.copy_from_slice(llrs);
+            if result.attempts == 0 {
+                for (i, llr) in modified.iter_mut().enumerate() {
+                    if vars[i/8] >> (7 - (i%8)) & 1 == 1 {
+                        *llr = T::zero();
+                    }
+                }
+            } else {
+                // Push the least reliable remaining variable, clearing it from `vars`
+                let mut var = n;
+                for (i, &llr) in llrs.iter().enumerate() {
+                    if vars[i/8] >> (7 - (i%8)) & 1 == 1
+                        && (var == n || magnitude(llr) < magnitude(llrs[var]))
+                    {
+                        var = i;
+                    }
+                }
+                if var == n {
+                    break;
+                }
+                vars[var/8] &= !(1 << (7 - (var%8)));
+                let bit = failed[var/8] >> (7 - (var%8)) & 1;
+                modified[var] = if bit == 1 { maxllr } else { -maxllr };
+            }
+
+            result.attempts += 1;
+            let (success, iters) = self.decode_ms(modified, output, working, working_u8,
+                                                  params.retry_iters);
+            result.iters += iters;
+            if success {
+                result.success = true;
+                break;
+            }
+        }
+
+        if !result.success {
+            output.copy_from_slice(failed);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::prelude::v1::*;
+
+    use ::codes::LDPCCode;
+    use ::sim::{AwgnChannel, Modulation, Rng};
+    use super::TrappingParams;
+
+    /// Decode `frames` random frames of `code` with `errors` confidently wrong bits each,
+    /// checking the trapping result against plain `decode_ms`. Returns the number of frames
+    /// retried, the number retried more than once, and the frames decoded correctly by
+    /// `decode_ms` and `decode_ms_trapping`.
+    fn check(code: LDPCCode, params: &TrappingParams, seed: u64, frames: usize, noise: f32,
+             errors: usize)
+        -> (usize, usize, [usize; 2])
+    {
+        let mut rng = Rng::new(seed);
+        let mut working = vec![0f32; code.decode_ms_working_len()];
+        let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+        let mut trapping_working = vec![0f32; code.decode_trapping_working_len()];
+        let mut trapping_working_u8 = vec![0u8; code.decode_trapping_working_u8_len()];
+        let mut output = vec![0u8; code.output_len()];
+        let mut correct = [0; 2];
+        let mut retried = 0;
+        let mut rescanned = 0;
+
+        for _ in 0..frames {
+            let mut txdata = vec![0u8; code.k()/8];
+            rng.fill_bytes(&mut txdata);
+            let mut txcode = vec![0u8; code.n()/8];
+            code.copy_encode(&txdata, &mut txcode);
+
+            // Mildly noisy LLRs plus a few confidently wrong bits, which tend to trap min-sum
+            let mut llrs = vec![0f32; code.n()];
+            code.hard_to_llrs(&txcode, &mut llrs);
+            for x in llrs.iter_mut() {
+                *x *= 1.0 + noise * rng.gaussian() as f32;
+            }
+            for _ in 0..errors {
+                let i = (rng.next_u64() % code.n() as u64) as usize;
+                llrs[i] = -llrs[i].signum() * 3.0;
+            }
+
+            let (success, _) = code.decode_ms(&llrs, &mut output, &mut working,
+                                              &mut working_u8, 50);
+            correct[0] += (success && output[..code.n()/8] == txcode[..]) as usize;
+            let result = code.decode_ms_trapping(&llrs, &mut output, &mut working,
+                                                 &mut working_u8, &mut trapping_working,
+                                                 &mut trapping_working_u8, 50, params);
+            correct[1] += (result.success && output[..code.n()/8] == txcode[..]) as usize;
+            if success {
+                assert!(result.success);
+                assert_eq!((result.unsatisfied, result.attempts), (0, 0));
+            } else if result.unsatisfied <= params.max_unsatisfied {
+                assert!(result.attempts > 0);
+                retried += 1;
+                rescanned += (result.attempts > 1) as usize;
+            } else {
+                assert_eq!(result.attempts, 0);
+            }
+        }
+
+        (retried, rescanned, correct)
+    }
+
+    #[test]
+    fn test_trapping() {
+        let params = TrappingParams::default();
+        let (retried, _, correct) = check(LDPCCode::TC128, &params, 11, 100, 0.5, 3);
+        assert!(retried > 0);
+        assert!(correct[1] > correct[0]);
+    }
+
+    #[test]
+    fn test_trapping_punctured() {
+        // Failed TM frames leave more unsatisfied checks, so allow more before giving up.
+        // Unsatisfied checks connect to punctured variables too, which the retries must skip.
+        let params = TrappingParams { max_unsatisfied: 32, ..Default::default() };
+        let (retried, rescanned, correct) = check(LDPCCode::TM1280, &params, 5, 20, 0.2, 5);
+        assert!(retried > 0 && rescanned > 0);
+        assert!(correct[1] > correct[0]);
+    }
+
+    #[test]
+    fn test_trapping_tm8192() {
+        // Near its threshold, the ninth frame from this channel is still a handful of wrong
+        // bits away from converging when min-sum runs out of iterations
+        let code = LDPCCode::TM8192;
+        let mut ch = AwgnChannel::new(code, Modulation::BPSK, 1.3, 1);
+        let txcode = vec![0u8; code.n()/8];
+        let mut llrs = vec![0f32; code.n()];
+        for _ in 0..9 {
+            ch.transmit(&txcode, &mut llrs, 1.0);
+        }
+
+        let params = TrappingParams::default();
+        let mut working = vec![0f32; code.decode_ms_working_len()];
+        let mut working_u8 = vec![0u8; code.decode_ms_working_u8_len()];
+        let mut trapping_working = vec![0f32; code.decode_trapping_working_len()];
+        let mut trapping_working_u8 = vec![0u8; code.decode_trapping_working_u8_len()];
+        let mut output = vec![0u8; code.output_len()];
+        let (success, _) = code.decode_ms(&llrs, &mut output, &mut working, &mut working_u8, 50);
+        assert!(!success);
+
+        let result = code.decode_ms_trapping(&llrs, &mut output, &mut working, &mut working_u8,
+                                             &mut trapping_working, &mut trapping_working_u8,
+                                             50, &params);
+        assert!(result.success);
+        assert!(result.unsatisfied <= params.max_unsatisfied);
+        assert!(result.attempts > 1);
+        assert_eq!(&output[..code.n()/8], &txcode[..]);
+    }
+}